@@ -0,0 +1,640 @@
+use regex::Regex;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+/// How the files in an LLM response are laid out.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum ResponseFormat {
+    /// Whichever of the formats below the response uses.
+    #[default]
+    Auto,
+    /// `// path` lines each followed by the whole file.
+    Plain,
+    /// `<file path="...">` tags around each whole file.
+    Xml,
+    /// Fenced code blocks, each under or starting with the file's path.
+    Markdown,
+    /// Unified diffs against the files on disk.
+    Diff,
+}
+
+/// A change to one file, as written in the response.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Edit {
+    /// As written in the response, relative to the working directory.
+    pub path: PathBuf,
+    pub kind: EditKind,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum EditKind {
+    /// The new contents of the whole file.
+    Replace(String),
+    /// A unified diff of one file; `deleted` when its new side is `/dev/null`.
+    Patch { patch: String, deleted: bool },
+}
+
+/// A file as it will be once the response is applied.
+pub struct FileChange {
+    /// Relative to the working directory, without a `./` prefix.
+    pub path: PathBuf,
+    /// `None` when the file does not exist yet.
+    pub old: Option<String>,
+    /// `None` when the file is deleted.
+    pub new: Option<String>,
+}
+
+impl FileChange {
+    /// A unified diff of the change, for previewing it.
+    #[must_use]
+    pub fn diff(&self) -> String {
+        let name = self.path.to_string_lossy();
+        let mut options = diffy::DiffOptions::new();
+        options
+            .set_original_filename(if self.old.is_some() {
+                format!("a/{name}")
+            } else {
+                "/dev/null".to_string()
+            })
+            .set_modified_filename(if self.new.is_some() {
+                format!("b/{name}")
+            } else {
+                "/dev/null".to_string()
+            });
+        options
+            .create_patch(
+                self.old.as_deref().unwrap_or_default(),
+                self.new.as_deref().unwrap_or_default(),
+            )
+            .to_string()
+    }
+}
+
+/// Finds every file in `response`. A `// path` header only names a file when it is
+/// written the way the prompt writes paths, starting with `./`, or when the file
+/// exists under `root`.
+///
+/// # Errors
+/// Returns an error if no file can be found in the response in the given format.
+pub fn parse(response: &str, format: ResponseFormat, root: &Path) -> Result<Vec<Edit>, String> {
+    let format = if format == ResponseFormat::Auto {
+        detect(response, root)
+    } else {
+        format
+    };
+    let edits = match format {
+        ResponseFormat::Xml => xml_files(response)?,
+        ResponseFormat::Diff => diff_files(response),
+        ResponseFormat::Markdown => markdown_files(response, root),
+        ResponseFormat::Plain | ResponseFormat::Auto => plain_files(response, root),
+    };
+    if edits.is_empty() {
+        return Err(format!(
+            "Found no files in the response ({format:?} format)"
+        ));
+    }
+    Ok(edits)
+}
+
+/// Works out what each edit does to the files under `root`, in order, so that
+/// several edits of one file build on each other.
+///
+/// # Errors
+/// Returns an error if a path leaves `root`, a file cannot be read, or a diff does not
+/// apply.
+pub fn plan(root: &Path, edits: &[Edit]) -> Result<Vec<FileChange>, String> {
+    let canonical_root = root
+        .canonicalize()
+        .map_err(|e| format!("{}: {e}", root.display()))?;
+    let mut files: BTreeMap<PathBuf, (Option<String>, Option<String>)> = BTreeMap::new();
+    for edit in edits {
+        let path = contained_path(&canonical_root, &edit.path)?;
+        if !files.contains_key(&path) {
+            let full = root.join(&path);
+            let old = if full.exists() {
+                Some(fs::read_to_string(&full).map_err(|e| format!("{}: {e}", path.display()))?)
+            } else {
+                None
+            };
+            files.insert(path.clone(), (old.clone(), old));
+        }
+        let Some((_, current)) = files.get_mut(&path) else {
+            continue;
+        };
+        *current = match &edit.kind {
+            EditKind::Replace(contents) => Some(contents.clone()),
+            EditKind::Patch { patch, deleted } => {
+                let parsed = diffy::Patch::from_str(patch)
+                    .map_err(|e| format!("{}: {e}", path.display()))?;
+                let patched = diffy::apply(current.as_deref().unwrap_or_default(), &parsed)
+                    .map_err(|e| format!("{}: the diff does not apply: {e}", path.display()))?;
+                (!deleted).then_some(patched)
+            }
+        };
+    }
+    Ok(files
+        .into_iter()
+        .filter(|(_, (old, new))| old != new)
+        .map(|(path, (old, new))| FileChange { path, old, new })
+        .collect())
+}
+
+/// Writes every change under `root`, first copying each file it overwrites or
+/// deletes into `backup`, under the same relative path. Returns the backups made.
+///
+/// # Errors
+/// Returns an error if a backup or a write fails; changes before it stay applied.
+pub fn apply(root: &Path, changes: &[FileChange], backup: &Path) -> Result<Vec<PathBuf>, String> {
+    let mut backups = Vec::new();
+    for change in changes {
+        let target = root.join(&change.path);
+        if change.old.is_some() {
+            let copy = backup.join(&change.path);
+            if let Some(parent) = copy.parent() {
+                fs::create_dir_all(parent).map_err(|e| format!("{}: {e}", parent.display()))?;
+            }
+            fs::copy(&target, &copy).map_err(|e| format!("{}: {e}", copy.display()))?;
+            backups.push(copy);
+        }
+        match &change.new {
+            Some(contents) => {
+                if let Some(parent) = target.parent() {
+                    fs::create_dir_all(parent).map_err(|e| format!("{}: {e}", parent.display()))?;
+                }
+                fs::write(&target, contents).map_err(|e| format!("{}: {e}", target.display()))?;
+            }
+            None => fs::remove_file(&target).map_err(|e| format!("{}: {e}", target.display()))?,
+        }
+    }
+    Ok(backups)
+}
+
+/// The format a response is most likely in: tags, then diffs, then fenced blocks.
+fn detect(response: &str, root: &Path) -> ResponseFormat {
+    let lines: Vec<&str> = response.lines().collect();
+    if response.contains("<file path=\"") {
+        ResponseFormat::Xml
+    } else if lines
+        .windows(2)
+        .any(|pair| pair[0].starts_with("--- ") && pair[1].starts_with("+++ "))
+    {
+        ResponseFormat::Diff
+    } else if !markdown_files(response, root).is_empty() {
+        ResponseFormat::Markdown
+    } else {
+        ResponseFormat::Plain
+    }
+}
+
+fn xml_files(response: &str) -> Result<Vec<Edit>, String> {
+    let tag =
+        Regex::new(r#"(?s)<file path="([^"]*)">\r?\n?(.*?)</file>"#).map_err(|e| e.to_string())?;
+    Ok(tag
+        .captures_iter(response)
+        .map(|captures| Edit {
+            path: PathBuf::from(unescape_attribute(&captures[1])),
+            kind: EditKind::Replace(strip_line_numbers(&captures[2])),
+        })
+        .collect())
+}
+
+/// Files laid out as `// path` lines, each after a blank line or at the start.
+fn plain_files(response: &str, root: &Path) -> Vec<Edit> {
+    let mut edits = Vec::new();
+    let mut current: Option<(PathBuf, Vec<&str>)> = None;
+    let mut previous_blank = true;
+    for line in response.lines() {
+        let header = previous_blank
+            .then(|| {
+                line.strip_prefix("// ")
+                    .and_then(|text| file_header(text, root))
+            })
+            .flatten();
+        if let Some(path) = header {
+            edits.extend(current.take().map(|(path, lines)| whole_file(path, &lines)));
+            current = Some((path, Vec::new()));
+        } else if let Some((_, lines)) = &mut current {
+            lines.push(line);
+        }
+        previous_blank = line.trim().is_empty();
+    }
+    edits.extend(current.map(|(path, lines)| whole_file(path, &lines)));
+    edits
+}
+
+/// Fenced code blocks named by the line just above them, such as `src/main.rs` or
+/// `**src/main.rs**`, or by a `// path` first line inside them.
+fn markdown_files(response: &str, root: &Path) -> Vec<Edit> {
+    let lines: Vec<&str> = response.lines().collect();
+    let mut edits = Vec::new();
+    let mut index = 0;
+    while index < lines.len() {
+        let fence_len = lines[index].len() - lines[index].trim_start_matches('`').len();
+        if fence_len < 3 {
+            index += 1;
+            continue;
+        }
+        let fence = &lines[index][..fence_len];
+        let close = lines[index + 1..]
+            .iter()
+            .position(|line| {
+                line.trim_end().starts_with(fence) && line.trim_end().trim_matches('`').is_empty()
+            })
+            .map_or(lines.len(), |offset| index + 1 + offset);
+        let body = &lines[index + 1..close];
+        let above = lines[..index]
+            .iter()
+            .rev()
+            .find(|line| !line.trim().is_empty())
+            .copied()
+            .unwrap_or_default();
+        let named_above = path_like(
+            above
+                .trim()
+                .trim_matches(|c| matches!(c, '#' | '*' | '`' | ':' | ' ')),
+        );
+        let named_inside = body
+            .first()
+            .and_then(|line| line.strip_prefix("// "))
+            .and_then(|text| file_header(text, root));
+        if let Some(path) = named_above {
+            edits.push(whole_file(path, body));
+        } else if let Some(path) = named_inside {
+            edits.push(whole_file(path, &body[1..]));
+        }
+        index = close + 1;
+    }
+    edits
+}
+
+/// Splits unified diffs into one patch per file, dropping any text around them. Each
+/// hunk takes as many lines as its `@@` header counts, so text right after a diff is
+/// not mistaken for part of it.
+fn diff_files(response: &str) -> Vec<Edit> {
+    let lines: Vec<&str> = response.lines().collect();
+    let mut edits = Vec::new();
+    let mut index = 0;
+    while index + 1 < lines.len() {
+        let (Some(old), Some(new)) = (
+            lines[index].strip_prefix("--- "),
+            lines[index + 1].strip_prefix("+++ "),
+        ) else {
+            index += 1;
+            continue;
+        };
+        let mut end = index + 2;
+        while let Some((mut old_lines, mut new_lines)) =
+            lines.get(end).and_then(|line| hunk_counts(line))
+        {
+            end += 1;
+            while end < lines.len() && (old_lines > 0 || new_lines > 0) {
+                match lines[end].as_bytes().first() {
+                    None | Some(b' ') => {
+                        old_lines = old_lines.saturating_sub(1);
+                        new_lines = new_lines.saturating_sub(1);
+                    }
+                    Some(b'-') => old_lines = old_lines.saturating_sub(1),
+                    Some(b'+') => new_lines = new_lines.saturating_sub(1),
+                    Some(b'\\') => {}
+                    _ => break,
+                }
+                end += 1;
+            }
+            while lines.get(end).is_some_and(|line| line.starts_with('\\')) {
+                end += 1;
+            }
+        }
+        let (old, new) = (diff_path(old), diff_path(new));
+        let deleted = new.is_none();
+        if let Some(path) = new.or(old) {
+            let mut patch = String::new();
+            for line in &lines[index..end] {
+                // Blank context lines often lose their leading space on the way back.
+                patch.push_str(if line.is_empty() { " " } else { line });
+                patch.push('\n');
+            }
+            edits.push(Edit {
+                path,
+                kind: EditKind::Patch { patch, deleted },
+            });
+        }
+        index = end;
+    }
+    edits
+}
+
+/// How many old and new lines the hunk of a `@@ -1,5 +1,6 @@` header spans.
+fn hunk_counts(header: &str) -> Option<(usize, usize)> {
+    let ranges = header.strip_prefix("@@ -")?.split(" @@").next()?;
+    let (old, new) = ranges.split_once(" +")?;
+    let count = |range: &str| {
+        range
+            .split_once(',')
+            .map_or(Some(1), |(_, count)| count.parse().ok())
+    };
+    Some((count(old)?, count(new)?))
+}
+
+/// The path on a `---` or `+++` line, without its `a/` or `b/` prefix and timestamp;
+/// `None` for `/dev/null`.
+fn diff_path(header: &str) -> Option<PathBuf> {
+    let path = header.split('\t').next().unwrap_or_default().trim();
+    if path == "/dev/null" {
+        return None;
+    }
+    let path = path
+        .strip_prefix("a/")
+        .or_else(|| path.strip_prefix("b/"))
+        .unwrap_or(path);
+    Some(PathBuf::from(path))
+}
+
+fn whole_file(path: PathBuf, lines: &[&str]) -> Edit {
+    let mut lines = lines;
+    while lines.last().is_some_and(|line| line.trim().is_empty()) {
+        lines = &lines[..lines.len() - 1];
+    }
+    let mut contents = lines.join("\n");
+    if !contents.is_empty() {
+        contents.push('\n');
+    }
+    Edit {
+        path,
+        kind: EditKind::Replace(strip_line_numbers(&contents)),
+    }
+}
+
+/// The path of a `// path` header: one starting with `./`, as the prompt writes it, or
+/// naming a file under `root`, so that a comment such as `// v1.2` is left alone.
+fn file_header(text: &str, root: &Path) -> Option<PathBuf> {
+    let path = path_like(text)?;
+    (text.trim().starts_with("./") || root.join(&path).is_file()).then_some(path)
+}
+
+/// A file name as the prompt writes it: no spaces, and an extension or a directory.
+fn path_like(text: &str) -> Option<PathBuf> {
+    let text = text.trim();
+    let plausible = !text.is_empty()
+        && !text.contains(char::is_whitespace)
+        && !text.contains("://")
+        && (text.contains('/') || text.contains('.'))
+        && !text.ends_with('/');
+    plausible.then(|| PathBuf::from(text))
+}
+
+/// Removes a `--line-numbers` gutter, if every line has one.
+fn strip_line_numbers(contents: &str) -> String {
+    if contents.is_empty() || !contents.lines().all(|line| gutter(line).is_some()) {
+        return contents.to_string();
+    }
+    let mut stripped: String = contents
+        .lines()
+        .map(|line| gutter(line).unwrap_or_default())
+        .collect::<Vec<_>>()
+        .join("\n");
+    if contents.ends_with('\n') {
+        stripped.push('\n');
+    }
+    stripped
+}
+
+/// The code after a `  42 | ` gutter, or `None` without one.
+fn gutter(line: &str) -> Option<&str> {
+    let rest = line.trim_start();
+    let digits = rest.len() - rest.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+    let after = &rest[digits..];
+    (digits > 0 && (after == " |" || after.starts_with(" | ")))
+        .then(|| after.get(3..).unwrap_or_default())
+}
+
+fn unescape_attribute(value: &str) -> String {
+    value
+        .replace("&quot;", "\"")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&amp;", "&")
+}
+
+/// `path` relative to `root`, refused if it is absolute or leads outside `root`,
+/// through `..` or a symbolic link.
+fn contained_path(root: &Path, path: &Path) -> Result<PathBuf, String> {
+    let outside = || format!("{} is outside the working directory", path.display());
+    let mut relative = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::Normal(name) => relative.push(name),
+            Component::ParentDir => {
+                if !relative.pop() {
+                    return Err(outside());
+                }
+            }
+            Component::RootDir | Component::Prefix(_) => return Err(outside()),
+        }
+    }
+    if relative.as_os_str().is_empty() {
+        return Err(format!("{} is not a file path", path.display()));
+    }
+    let existing = root
+        .join(&relative)
+        .ancestors()
+        .find(|ancestor| ancestor.exists())
+        .map(Path::canonicalize);
+    match existing {
+        Some(Ok(existing)) if existing.starts_with(root) => Ok(relative),
+        _ => Err(outside()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An empty directory under the system's temporary one, unique to `name`.
+    fn scratch(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("llm-prompt-apply-{}-{name}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir.canonicalize().unwrap()
+    }
+
+    fn replace(path: &str, contents: &str) -> Edit {
+        Edit {
+            path: PathBuf::from(path),
+            kind: EditKind::Replace(contents.to_string()),
+        }
+    }
+
+    #[test]
+    fn plain_splits_files_at_prompt_headers() {
+        let root = scratch("plain-split");
+        let response = "// ./src/a.rs\nfn a() {}\n\n// ./src/b.rs\nfn b() {}\n";
+        let edits = parse(response, ResponseFormat::Plain, &root).unwrap();
+        assert_eq!(
+            edits,
+            vec![
+                replace("./src/a.rs", "fn a() {}\n"),
+                replace("./src/b.rs", "fn b() {}\n")
+            ]
+        );
+    }
+
+    #[test]
+    fn plain_keeps_comments_that_are_not_files() {
+        let root = scratch("plain-comment");
+        let response = "// ./src/a.rs\nuse std::fmt;\n\n// v1.2\nfn a() {}\n";
+        let edits = parse(response, ResponseFormat::Plain, &root).unwrap();
+        assert_eq!(
+            edits,
+            vec![replace(
+                "./src/a.rs",
+                "use std::fmt;\n\n// v1.2\nfn a() {}\n"
+            )]
+        );
+    }
+
+    #[test]
+    fn plain_accepts_existing_files_without_prefix() {
+        let root = scratch("plain-existing");
+        fs::create_dir_all(root.join("src")).unwrap();
+        fs::write(root.join("src/b.rs"), "").unwrap();
+        let response = "// ./src/a.rs\nfn a() {}\n\n// src/b.rs\nfn b() {}\n";
+        let edits = parse(response, ResponseFormat::Plain, &root).unwrap();
+        assert_eq!(
+            edits,
+            vec![
+                replace("./src/a.rs", "fn a() {}\n"),
+                replace("src/b.rs", "fn b() {}\n")
+            ]
+        );
+    }
+
+    #[test]
+    fn plain_strips_line_numbers() {
+        let root = scratch("plain-numbers");
+        let response = "// ./src/a.rs\n 9 | fn a() {}\n10 | \n11 | fn b() {}\n";
+        let edits = parse(response, ResponseFormat::Plain, &root).unwrap();
+        assert_eq!(
+            edits,
+            vec![replace("./src/a.rs", "fn a() {}\n\nfn b() {}\n")]
+        );
+    }
+
+    #[test]
+    fn markdown_names_blocks_from_above_or_inside() {
+        let root = scratch("markdown");
+        let response = "Here you go.\n\n**src/a.rs**\n```rust\nfn a() {}\n```\n\n```rust\n// ./src/b.rs\nfn b() {}\n```\n";
+        let edits = parse(response, ResponseFormat::Auto, &root).unwrap();
+        assert_eq!(
+            edits,
+            vec![
+                replace("src/a.rs", "fn a() {}\n"),
+                replace("./src/b.rs", "fn b() {}\n")
+            ]
+        );
+    }
+
+    #[test]
+    fn markdown_ignores_unnamed_blocks() {
+        let root = scratch("markdown-unnamed");
+        let response = "Run this:\n\n```sh\ncargo test\n```\n\n```rust\n// v1.2\nfn a() {}\n```\n";
+        assert!(markdown_files(response, &root).is_empty());
+    }
+
+    #[test]
+    fn markdown_keeps_longer_fences_whole() {
+        let root = scratch("markdown-fence");
+        let response = "README.md\n````markdown\n```sh\ncargo test\n```\n````\n";
+        let edits = parse(response, ResponseFormat::Markdown, &root).unwrap();
+        assert_eq!(
+            edits,
+            vec![replace("README.md", "```sh\ncargo test\n```\n")]
+        );
+    }
+
+    #[test]
+    fn diff_stops_each_hunk_at_its_line_counts() {
+        let root = scratch("diff-bounded");
+        fs::write(root.join("a.rs"), "fn a() -> u8 {\n    0\n}\n").unwrap();
+        let response = "--- a/a.rs\n+++ b/a.rs\n@@ -1,3 +1,3 @@\n fn a() -> u8 {\n-    0\n+    1\n }\n- Changed a to return 1\n";
+        let edits = parse(response, ResponseFormat::Auto, &root).unwrap();
+        assert_eq!(edits.len(), 1);
+        let changes = plan(&root, &edits).unwrap();
+        assert_eq!(
+            changes[0].new.as_deref(),
+            Some("fn a() -> u8 {\n    1\n}\n")
+        );
+    }
+
+    #[test]
+    fn diff_splits_files_and_deletes() {
+        let root = scratch("diff-files");
+        fs::write(root.join("a.rs"), "one\ntwo\n").unwrap();
+        fs::write(root.join("b.rs"), "gone\n").unwrap();
+        let response = "\
+--- a/a.rs
++++ b/a.rs
+@@ -1,2 +1,2 @@
+ one
+-two
++three
+
+--- a/b.rs
++++ /dev/null
+@@ -1 +0,0 @@
+-gone
+";
+        let edits = parse(response, ResponseFormat::Diff, &root).unwrap();
+        assert_eq!(edits.len(), 2);
+        assert_eq!(
+            edits[1].kind,
+            EditKind::Patch {
+                patch: "--- a/b.rs\n+++ /dev/null\n@@ -1 +0,0 @@\n-gone\n".to_string(),
+                deleted: true
+            }
+        );
+        let changes = plan(&root, &edits).unwrap();
+        assert_eq!(changes[0].new.as_deref(), Some("one\nthree\n"));
+        assert_eq!(changes[1].new, None);
+    }
+
+    #[test]
+    fn hunk_counts_default_to_one() {
+        assert_eq!(hunk_counts("@@ -3 +3,2 @@ fn a()"), Some((1, 2)));
+        assert_eq!(hunk_counts("@@ -1,0 +1 @@"), Some((0, 1)));
+        assert_eq!(hunk_counts("- a bullet"), None);
+    }
+
+    #[test]
+    fn contained_path_normalizes_relative_paths() {
+        let root = scratch("contained");
+        assert_eq!(
+            contained_path(&root, Path::new("./src/../a.rs")).unwrap(),
+            PathBuf::from("a.rs")
+        );
+        assert_eq!(
+            contained_path(&root, Path::new("new/dir/b.rs")).unwrap(),
+            PathBuf::from("new/dir/b.rs")
+        );
+    }
+
+    #[test]
+    fn contained_path_refuses_escapes() {
+        let root = scratch("escapes");
+        assert!(contained_path(&root, Path::new("../a.rs")).is_err());
+        assert!(contained_path(&root, Path::new("src/../../a.rs")).is_err());
+        assert!(contained_path(&root, Path::new("/etc/passwd")).is_err());
+        assert!(contained_path(&root, Path::new(".")).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn contained_path_refuses_symlinks_out() {
+        let root = scratch("symlink");
+        let outside = scratch("symlink-target");
+        std::os::unix::fs::symlink(&outside, root.join("link")).unwrap();
+        assert!(contained_path(&root, Path::new("link/a.rs")).is_err());
+    }
+}
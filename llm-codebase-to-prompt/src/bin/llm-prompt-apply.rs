@@ -0,0 +1,110 @@
+use clap::Parser;
+use llm_codebase_to_prompt::apply::{self, ResponseFormat};
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+/// Applies the files of an LLM response, written in the format of the prompt, to the
+/// codebase: whole files as `// path` blocks, `<file>` tags or fenced blocks, or
+/// unified diffs.
+#[derive(Parser)]
+#[command(name = "llm-prompt-apply", version, about, long_about = None)]
+struct Cli {
+    /// File holding the response; read from stdin when absent or `-`.
+    response: Option<PathBuf>,
+
+    /// Directory the paths in the response are relative to. Nothing outside it is written.
+    #[arg(long, short = 'C', default_value = ".")]
+    working_directory: PathBuf,
+
+    #[arg(long, value_enum, default_value_t)]
+    format: ResponseFormat,
+
+    /// Show the diff of every change without writing anything.
+    #[arg(long)]
+    dry_run: bool,
+
+    /// Where to copy the files that are overwritten or deleted. Defaults to
+    /// `.llm-prompt-backup/<timestamp>` in the working directory.
+    #[arg(long)]
+    backup_dir: Option<PathBuf>,
+}
+
+fn main() -> ExitCode {
+    let args = Cli::parse();
+    let response = match read_response(&args) {
+        Ok(response) => response,
+        Err(e) => return fail("Error reading the response", &e),
+    };
+    let changes = match apply::parse(&response, args.format, &args.working_directory)
+        .and_then(|edits| apply::plan(&args.working_directory, &edits))
+    {
+        Ok(changes) => changes,
+        Err(e) => return fail("Error reading the changes", &e),
+    };
+    if changes.is_empty() {
+        println!("The response changes nothing.");
+        return ExitCode::SUCCESS;
+    }
+
+    for change in &changes {
+        print!("{}", change.diff());
+    }
+    if args.dry_run {
+        println!("Would change {} files.", changes.len());
+        return ExitCode::SUCCESS;
+    }
+
+    let backup = args
+        .backup_dir
+        .clone()
+        .unwrap_or_else(|| backup_dir(&args.working_directory));
+    match apply::apply(&args.working_directory, &changes, &backup) {
+        Ok(backups) => {
+            println!("Changed {} files.", changes.len());
+            if !backups.is_empty() {
+                println!("Backed up {} files to {}", backups.len(), backup.display());
+            }
+            ExitCode::SUCCESS
+        }
+        Err(e) => fail("Error applying the changes", &e),
+    }
+}
+
+/// A fresh `.llm-prompt-backup/<timestamp>` directory, numbered if an earlier run in
+/// the same second already made one.
+fn backup_dir(root: &Path) -> PathBuf {
+    let base = root
+        .join(".llm-prompt-backup")
+        .join(chrono::Local::now().format("%Y%m%dT%H%M%S").to_string());
+    (1..1000)
+        .map(|attempt| {
+            if attempt == 1 {
+                base.clone()
+            } else {
+                base.with_extension(attempt.to_string())
+            }
+        })
+        .find(|dir| !dir.exists())
+        .unwrap_or(base)
+}
+
+fn read_response(args: &Cli) -> Result<String, String> {
+    match &args.response {
+        Some(path) if path.as_os_str() != "-" => {
+            std::fs::read_to_string(path).map_err(|e| format!("{}: {e}", path.display()))
+        }
+        _ => {
+            let mut response = String::new();
+            io::stdin()
+                .read_to_string(&mut response)
+                .map_err(|e| e.to_string())?;
+            Ok(response)
+        }
+    }
+}
+
+fn fail(context: &str, e: &str) -> ExitCode {
+    eprintln!("{context}: {e}");
+    ExitCode::FAILURE
+}
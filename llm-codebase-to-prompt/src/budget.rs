@@ -0,0 +1,258 @@
+use crate::tokens::Tokenizer;
+use crate::SourceFile;
+#[cfg(feature = "logging")]
+use log::info;
+use log::warn;
+use std::fmt::Write;
+
+/// Marker appended to a file whose contents were cut to fit the token budget.
+const TRUNCATION_MARKER: &str = "[... truncated to fit the token budget ...]";
+
+/// What to do when the prompt is larger than `--max-tokens`.
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    Default,
+    PartialEq,
+    Eq,
+    clap::ValueEnum,
+    serde::Serialize,
+    serde::Deserialize,
+)]
+#[serde(rename_all = "kebab-case")]
+pub enum OverBudget {
+    /// Drop the largest files until the prompt fits.
+    DropLargest,
+    /// Cut the largest files short until the prompt fits.
+    Truncate,
+    /// Refuse to write the prompt and report the token count of every file.
+    #[default]
+    Fail,
+}
+
+/// Brings the files of every section under `max_tokens`, leaving `overhead` tokens for
+/// the section contexts. Returns the paths of files that were dropped or truncated.
+///
+/// # Errors
+/// Returns a per-file breakdown if the policy is [`OverBudget::Fail`] and the prompt
+/// is over budget, or if the overhead alone does not fit.
+pub fn enforce(
+    sections: &mut [Vec<SourceFile>],
+    overhead: usize,
+    max_tokens: usize,
+    policy: OverBudget,
+    tokenizer: &Tokenizer,
+) -> Result<Vec<String>, String> {
+    let mut changed = Vec::new();
+    loop {
+        let total = overhead
+            + sections
+                .iter()
+                .flatten()
+                .map(|file| file.tokens)
+                .sum::<usize>();
+        if total <= max_tokens {
+            return Ok(changed);
+        }
+        let excess = total - max_tokens;
+
+        let Some((section, index)) = largest(sections) else {
+            return Err(format!(
+                "Prompt context alone is {overhead} tokens, over the budget of {max_tokens}"
+            ));
+        };
+        match policy {
+            OverBudget::Fail => return Err(breakdown(sections, total, max_tokens)),
+            OverBudget::DropLargest => {
+                let file = sections[section].remove(index);
+                warn!(
+                    "Dropping [{}] ({} tokens) to fit the token budget",
+                    file.path.display(),
+                    file.tokens
+                );
+                changed.push(format!("dropped {}", file.path.display()));
+            }
+            OverBudget::Truncate => {
+                let file = &mut sections[section][index];
+                let allowed = file.tokens.saturating_sub(excess);
+                if truncate(file, allowed, tokenizer) {
+                    warn!(
+                        "Truncating [{}] to {} tokens to fit the token budget",
+                        file.path.display(),
+                        file.tokens
+                    );
+                    changed.push(format!("truncated {}", file.path.display()));
+                } else {
+                    let file = sections[section].remove(index);
+                    warn!(
+                        "Dropping [{}] ({} tokens): too large to truncate into the budget",
+                        file.path.display(),
+                        file.tokens
+                    );
+                    changed.push(format!("dropped {}", file.path.display()));
+                }
+            }
+        }
+    }
+}
+
+/// Finds the section and index of the file with the most tokens.
+fn largest(sections: &[Vec<SourceFile>]) -> Option<(usize, usize)> {
+    sections
+        .iter()
+        .enumerate()
+        .flat_map(|(section, files)| {
+            files
+                .iter()
+                .enumerate()
+                .map(move |(index, file)| (section, index, file.tokens))
+        })
+        .max_by_key(|&(_, _, tokens)| tokens)
+        .map(|(section, index, _)| (section, index))
+}
+
+/// Keeps as many whole lines of `file` as fit in `allowed` tokens. Returns `false`
+/// if not even the first line fits, or if the file did not get any smaller.
+fn truncate(file: &mut SourceFile, allowed: usize, tokenizer: &Tokenizer) -> bool {
+    let marker = tokenizer.count(TRUNCATION_MARKER) + 1;
+    let header = file.tokens.saturating_sub(tokenizer.count(&file.contents));
+    let Some(mut remaining) = allowed.checked_sub(header + marker) else {
+        return false;
+    };
+
+    let mut kept = 0;
+    for line in file.contents.split_inclusive('\n') {
+        let cost = tokenizer.count(line);
+        if cost > remaining {
+            break;
+        }
+        remaining -= cost;
+        kept += line.len();
+    }
+    if kept == 0 {
+        return false;
+    }
+
+    let before = file.tokens;
+    file.contents.truncate(kept);
+    if !file.contents.ends_with('\n') {
+        file.contents.push('\n');
+    }
+    file.contents.push_str(TRUNCATION_MARKER);
+    file.tokens = header + tokenizer.count(&file.contents);
+    #[cfg(feature = "logging")]
+    info!(
+        "Truncated [{}] from {} to {} tokens",
+        file.path.display(),
+        before,
+        file.tokens
+    );
+    file.tokens < before
+}
+
+/// Formats the token count of every file, largest first.
+fn breakdown(sections: &[Vec<SourceFile>], total: usize, max_tokens: usize) -> String {
+    let mut files: Vec<&SourceFile> = sections.iter().flatten().collect();
+    files.sort_by_key(|file| std::cmp::Reverse(file.tokens));
+
+    let mut message = format!("Prompt is {total} tokens, over the budget of {max_tokens}:\n");
+    for file in files {
+        let _ = writeln!(message, "{:>10}  {}", file.tokens, file.path.display());
+    }
+    message
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn file(path: &str, lines: usize, tokenizer: &Tokenizer) -> SourceFile {
+        let mut contents = String::new();
+        for line in 0..lines {
+            let _ = writeln!(contents, "let value_{line} = {line};");
+        }
+        SourceFile {
+            path: PathBuf::from(path),
+            tokens: tokenizer.count(&contents),
+            contents,
+            line_range: None,
+        }
+    }
+
+    fn total(sections: &[Vec<SourceFile>]) -> usize {
+        sections.iter().flatten().map(|file| file.tokens).sum()
+    }
+
+    #[test]
+    fn fail_reports_every_file_largest_first() {
+        let tokenizer = Tokenizer::default();
+        let mut sections = vec![vec![
+            file("small.rs", 2, &tokenizer),
+            file("large.rs", 20, &tokenizer),
+        ]];
+        let error = enforce(&mut sections, 0, 10, OverBudget::Fail, &tokenizer).unwrap_err();
+        let lines: Vec<&str> = error.lines().collect();
+        assert!(lines[0].starts_with("Prompt is "));
+        assert!(lines[1].ends_with("large.rs"));
+        assert!(lines[2].ends_with("small.rs"));
+        assert_eq!(sections[0].len(), 2);
+    }
+
+    #[test]
+    fn drop_largest_removes_files_until_the_prompt_fits() {
+        let tokenizer = Tokenizer::default();
+        let small = file("small.rs", 2, &tokenizer);
+        let budget = small.tokens + 5;
+        let mut sections = vec![
+            vec![small, file("large.rs", 20, &tokenizer)],
+            vec![file("medium.rs", 10, &tokenizer)],
+        ];
+        let changed = enforce(
+            &mut sections,
+            5,
+            budget,
+            OverBudget::DropLargest,
+            &tokenizer,
+        )
+        .unwrap();
+        assert_eq!(changed, ["dropped large.rs", "dropped medium.rs"]);
+        assert_eq!(sections[0][0].path, PathBuf::from("small.rs"));
+        assert!(sections[1].is_empty());
+    }
+
+    #[test]
+    fn truncate_keeps_whole_lines_and_appends_the_marker() {
+        let tokenizer = Tokenizer::default();
+        let mut sections = vec![vec![file("large.rs", 20, &tokenizer)]];
+        let budget = sections[0][0].tokens / 2;
+        let changed = enforce(&mut sections, 0, budget, OverBudget::Truncate, &tokenizer).unwrap();
+        assert_eq!(changed, ["truncated large.rs"]);
+        let truncated = &sections[0][0];
+        assert!(truncated.contents.starts_with("let value_0 = 0;\n"));
+        assert!(truncated
+            .contents
+            .ends_with(&format!(";\n{TRUNCATION_MARKER}")));
+        assert!(total(&sections) <= budget);
+    }
+
+    #[test]
+    fn truncate_drops_files_whose_first_line_does_not_fit() {
+        let tokenizer = Tokenizer::default();
+        let mut sections = vec![vec![file("large.rs", 20, &tokenizer)]];
+        let changed = enforce(&mut sections, 0, 3, OverBudget::Truncate, &tokenizer).unwrap();
+        assert_eq!(changed, ["dropped large.rs"]);
+        assert!(sections[0].is_empty());
+    }
+
+    #[test]
+    fn overhead_alone_over_budget_is_an_error() {
+        let tokenizer = Tokenizer::default();
+        let error = enforce(&mut [], 20, 10, OverBudget::DropLargest, &tokenizer).unwrap_err();
+        assert_eq!(
+            error,
+            "Prompt context alone is 20 tokens, over the budget of 10"
+        );
+    }
+}
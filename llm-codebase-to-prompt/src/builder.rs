@@ -0,0 +1,154 @@
+use crate::{
+    build_prompt, chunk, list_files, stream_prompt, template, ChunkLimit, Error, Options,
+    OutputFormat, OverBudget, Prompt, Report, Section, SectionConfig, Selection, Tokenizer,
+};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// Generates a prompt from the files under a root directory.
+///
+/// ```no_run
+/// use llm_codebase_to_prompt::{OutputFormat, PromptBuilder, SectionConfig};
+///
+/// let source = SectionConfig {
+///     name: "source".to_string(),
+///     include: vec!["src/**/*.rs".to_string()],
+///     context: Some("The following are the relevant source code files:".to_string()),
+///     ..SectionConfig::default()
+/// };
+/// let mut prompt = Vec::new();
+/// let report = PromptBuilder::new("path/to/project")
+///     .section(source)
+///     .format(OutputFormat::Markdown)
+///     .write(&mut prompt)?;
+/// println!("{} files, {} tokens", report.included.len(), report.total);
+/// # Ok::<(), llm_codebase_to_prompt::Error>(())
+/// ```
+pub struct PromptBuilder {
+    root: PathBuf,
+    sections: Vec<SectionConfig>,
+    options: Options,
+}
+
+impl PromptBuilder {
+    /// Starts a prompt over the files under `root`, with no sections and default options.
+    #[must_use]
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self {
+            root: root.into(),
+            sections: Vec::new(),
+            options: Options::default(),
+        }
+    }
+
+    /// Adds a section; sections are written in the order they are added.
+    #[must_use]
+    pub fn section(mut self, section: SectionConfig) -> Self {
+        self.sections.push(section);
+        self
+    }
+
+    /// Adds several sections.
+    #[must_use]
+    pub fn sections(mut self, sections: impl IntoIterator<Item = SectionConfig>) -> Self {
+        self.sections.extend(sections);
+        self
+    }
+
+    /// Replaces every option at once.
+    #[must_use]
+    pub fn options(mut self, options: Options) -> Self {
+        self.options = options;
+        self
+    }
+
+    #[must_use]
+    pub const fn format(mut self, format: OutputFormat) -> Self {
+        self.options.format = format;
+        self
+    }
+
+    #[must_use]
+    pub fn tokenizer(mut self, tokenizer: Tokenizer) -> Self {
+        self.options.tokenizer = tokenizer;
+        self
+    }
+
+    /// Keeps the prompt under `max_tokens`, applying `policy` when it does not fit.
+    #[must_use]
+    pub const fn max_tokens(mut self, max_tokens: usize, policy: OverBudget) -> Self {
+        self.options.max_tokens = Some(max_tokens);
+        self.options.over_budget = policy;
+        self
+    }
+
+    #[must_use]
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Reads, transforms and budgets every file without writing anything, for callers
+    /// that lay the prompt out themselves, such as when splitting it into parts.
+    ///
+    /// # Errors
+    /// Returns an error if a pattern is invalid, the directory walk or git lookup fails,
+    /// or the prompt does not fit the budget under the chosen policy.
+    pub fn build(&self) -> Result<Prompt, Error> {
+        build_prompt(&self.root, &self.borrowed_sections(), &self.options)
+    }
+
+    /// Writes the prompt to `output`, laid out by the template if there is one, and
+    /// reports what went into it.
+    ///
+    /// Unless the budget, a query, a template, an update, the tree or the statistics
+    /// need every file first, each file is written as soon as it is read, so only a
+    /// batch of files is held at once and a failure can leave a partial prompt.
+    ///
+    /// # Errors
+    /// Returns an error if the prompt cannot be built, the template fails, or writing
+    /// to `output` fails.
+    pub fn write(&self, output: &mut impl Write) -> Result<Report, Error> {
+        if self.options.streams() {
+            return stream_prompt(&self.root, &self.borrowed_sections(), &self.options, output);
+        }
+        let mut prompt = self.build()?;
+        match &self.options.template {
+            Some(template) => {
+                let rendered = template::render(template, &prompt, self.options.format)
+                    .map_err(Error::Template)?;
+                output.write_all(rendered.as_bytes())?;
+                prompt.report.total = self.options.tokenizer.count(&rendered);
+            }
+            None => prompt.write(output, self.options.format)?,
+        }
+        Ok(prompt.report)
+    }
+
+    /// Builds the prompt and splits it into parts that each stay under `limit`.
+    ///
+    /// # Errors
+    /// Returns an error if the prompt cannot be built, or a template lays it out.
+    pub fn split(&self, limit: ChunkLimit) -> Result<(Vec<String>, Report), Error> {
+        if self.options.template.is_some() {
+            return Err(Error::Template(
+                "a prompt laid out by a template cannot be split into parts".to_string(),
+            ));
+        }
+        let prompt = self.build()?;
+        let parts = chunk::split(&prompt, limit, self.options.format, &self.options.tokenizer);
+        Ok((parts, prompt.report))
+    }
+
+    /// Lists, for each section, every file its rules matched and whether it was
+    /// included or excluded, without reading anything.
+    ///
+    /// # Errors
+    /// Returns an error if a pattern is invalid or the directory walk fails.
+    pub fn list(&self) -> Result<Vec<Vec<(PathBuf, Selection)>>, Error> {
+        list_files(&self.root, &self.borrowed_sections(), &self.options)
+    }
+
+    fn borrowed_sections(&self) -> Vec<Section<'_>> {
+        self.sections.iter().map(SectionConfig::section).collect()
+    }
+}
@@ -0,0 +1,371 @@
+use crate::{OutputFormat, Prompt, SourceFile, Tokenizer};
+use std::path::Path;
+use syn::spanned::Spanned;
+
+/// The most each chunk of a split prompt may hold.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ChunkLimit {
+    Tokens(usize),
+    Bytes(usize),
+}
+
+impl ChunkLimit {
+    const fn value(self) -> usize {
+        match self {
+            Self::Tokens(limit) | Self::Bytes(limit) => limit,
+        }
+    }
+
+    fn measure(self, text: &str, tokenizer: &Tokenizer) -> usize {
+        match self {
+            Self::Tokens(_) => tokenizer.count(text),
+            Self::Bytes(_) => text.len(),
+        }
+    }
+}
+
+/// A piece of the prompt that is never split further: the header, or a file or part
+/// of a file belonging to a section.
+struct Piece {
+    section: Option<usize>,
+    file: Option<SourceFile>,
+    size: usize,
+}
+
+/// Splits a prompt into parts that each stay under `limit`.
+///
+/// Parts break only between files, or between functions when a single file is too
+/// big. Every part starts with a "part N of M" header, and repeats the context of each
+/// section it holds files of; the last part carries every context.
+#[must_use]
+pub fn split(
+    prompt: &Prompt,
+    limit: ChunkLimit,
+    format: OutputFormat,
+    tokenizer: &Tokenizer,
+) -> Vec<String> {
+    let reserve = limit.measure(&part_header(999, 999), tokenizer)
+        + prompt
+            .sections
+            .iter()
+            .map(|section| limit.measure(&format!("[\n\n]\n{}\n\n", section.context), tokenizer))
+            .sum::<usize>();
+    let available = limit.value().saturating_sub(reserve).max(1);
+
+    let mut pieces = Vec::new();
+    if !prompt.header.is_empty() {
+        pieces.push(Piece {
+            section: None,
+            file: None,
+            size: limit.measure(&prompt.header, tokenizer),
+        });
+    }
+    for (index, section) in prompt.sections.iter().enumerate() {
+        for file in &section.files {
+            let size = limit.measure(&file.render(format), tokenizer);
+            let parts = if size > available {
+                split_file(file, available, limit, format, tokenizer)
+            } else {
+                vec![file.clone()]
+            };
+            for part in parts {
+                let size = limit.measure(&part.render(format), tokenizer);
+                pieces.push(Piece {
+                    section: Some(index),
+                    file: Some(part),
+                    size,
+                });
+            }
+        }
+    }
+
+    let mut chunks: Vec<Vec<Piece>> = vec![Vec::new()];
+    let mut used = 0;
+    for piece in pieces {
+        if used + piece.size > available && chunks.last().is_some_and(|chunk| !chunk.is_empty()) {
+            chunks.push(Vec::new());
+            used = 0;
+        }
+        used += piece.size;
+        if let Some(chunk) = chunks.last_mut() {
+            chunk.push(piece);
+        }
+    }
+
+    let total = chunks.len();
+    chunks
+        .into_iter()
+        .enumerate()
+        .map(|(index, pieces)| render_chunk(prompt, &pieces, index + 1, total, format))
+        .collect()
+}
+
+fn render_chunk(
+    prompt: &Prompt,
+    pieces: &[Piece],
+    part: usize,
+    total: usize,
+    format: OutputFormat,
+) -> String {
+    let mut output = Vec::new();
+    output.extend_from_slice(part_header(part, total).as_bytes());
+    if pieces.iter().any(|piece| piece.section.is_none()) {
+        output.extend_from_slice(prompt.header.as_bytes());
+    }
+    for (index, section) in prompt.sections.iter().enumerate() {
+        let files: Vec<SourceFile> = pieces
+            .iter()
+            .filter(|piece| piece.section == Some(index))
+            .filter_map(|piece| piece.file.clone())
+            .collect();
+        if files.is_empty() && part < total {
+            continue;
+        }
+        if !files.is_empty() {
+            let _ = format.write_files(&mut output, &files);
+        }
+        output.extend_from_slice(format!("{}\n\n", section.context).as_bytes());
+    }
+    String::from_utf8_lossy(&output).into_owned()
+}
+
+fn part_header(part: usize, total: usize) -> String {
+    if part < total {
+        format!(
+            "[Part {part} of {total}]\nThis is part {part} of {total} of a prompt split into several messages. \
+             Do not answer yet: reply only with \"Received part {part} of {total}\" and wait for the next part.\n\n"
+        )
+    } else {
+        format!(
+            "[Part {part} of {total}]\nThis is the last part of a prompt split into {total} messages. \
+             Answer using the contents of every part.\n\n"
+        )
+    }
+}
+
+/// Splits one file into parts that fit `available`, preferring to break between
+/// top-level items and falling back to line boundaries.
+fn split_file(
+    file: &SourceFile,
+    available: usize,
+    limit: ChunkLimit,
+    format: OutputFormat,
+    tokenizer: &Tokenizer,
+) -> Vec<SourceFile> {
+    let empty = SourceFile {
+        contents: String::new(),
+        line_range: Some((1, 1)),
+        ..file.clone()
+    };
+    let space = available
+        .saturating_sub(limit.measure(&empty.render(format), tokenizer))
+        .max(1);
+
+    let mut segments = Vec::new();
+    for segment in segments_at(&file.contents, &boundaries(&file.path, &file.contents)) {
+        if limit.measure(segment, tokenizer) > space {
+            segments.extend(segment.split_inclusive('\n'));
+        } else {
+            segments.push(segment);
+        }
+    }
+
+    let first_line = file.line_range.map_or(1, |(first, _)| first);
+    let mut parts = Vec::new();
+    let mut contents = String::new();
+    let mut line = first_line;
+    for segment in segments {
+        if !contents.is_empty()
+            && limit.measure(&contents, tokenizer) + limit.measure(segment, tokenizer) > space
+        {
+            parts.push(part(file, std::mem::take(&mut contents), &mut line));
+        }
+        contents.push_str(segment);
+    }
+    if !contents.is_empty() {
+        parts.push(part(file, contents, &mut line));
+    }
+    parts
+}
+
+fn part(file: &SourceFile, contents: String, line: &mut usize) -> SourceFile {
+    let lines = contents.lines().count().max(1);
+    let range = (*line, *line + lines - 1);
+    *line += lines;
+    SourceFile {
+        path: file.path.clone(),
+        contents,
+        tokens: 0,
+        line_range: Some(range),
+    }
+}
+
+fn segments_at<'a>(contents: &'a str, boundaries: &[usize]) -> Vec<&'a str> {
+    let mut segments = Vec::new();
+    let mut start = 0;
+    for &boundary in boundaries
+        .iter()
+        .filter(|&&boundary| boundary > 0 && boundary < contents.len())
+    {
+        if boundary > start {
+            segments.push(&contents[start..boundary]);
+            start = boundary;
+        }
+    }
+    segments.push(&contents[start..]);
+    segments
+}
+
+/// Byte offsets where the file may be split: for Rust, the start of every item, and of
+/// every item inside an `impl`, a `trait` or an inline `mod`; otherwise the start of
+/// every paragraph after a blank line.
+fn boundaries(path: &Path, contents: &str) -> Vec<usize> {
+    if path.extension().is_some_and(|extension| extension == "rs") {
+        let items = syn::parse_file(contents).ok().map(|file| {
+            let mut starts = Vec::new();
+            item_starts(&file.items, &mut starts);
+            let mut boundaries: Vec<usize> = starts
+                .into_iter()
+                .map(|start| line_start(contents, start))
+                .collect();
+            boundaries.sort_unstable();
+            boundaries.dedup();
+            boundaries
+        });
+        // Drop the copy of the source that span locations hold on to.
+        proc_macro2::extra::invalidate_current_thread_spans();
+        if let Some(items) = items {
+            return items;
+        }
+    }
+
+    let mut boundaries = Vec::new();
+    let mut offset = 0;
+    let mut previous_blank = false;
+    for line in contents.split_inclusive('\n') {
+        let blank = line.trim().is_empty();
+        if previous_blank && !blank {
+            boundaries.push(offset);
+        }
+        previous_blank = blank;
+        offset += line.len();
+    }
+    boundaries
+}
+
+/// Collects where each item starts, doc comments and attributes included, descending
+/// into the items of `impl` blocks, traits and inline modules.
+fn item_starts(items: &[syn::Item], starts: &mut Vec<usize>) {
+    for item in items {
+        starts.push(item.span().byte_range().start);
+        match item {
+            syn::Item::Impl(item) => {
+                starts.extend(item.items.iter().map(|item| item.span().byte_range().start));
+            }
+            syn::Item::Trait(item) => {
+                starts.extend(item.items.iter().map(|item| item.span().byte_range().start));
+            }
+            syn::Item::Mod(syn::ItemMod {
+                content: Some((_, items)),
+                ..
+            }) => item_starts(items, starts),
+            _ => {}
+        }
+    }
+}
+
+fn line_start(contents: &str, offset: usize) -> usize {
+    contents[..offset.min(contents.len())]
+        .rfind('\n')
+        .map_or(0, |newline| newline + 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fmt::Write as _;
+    use std::path::PathBuf;
+
+    fn file(path: &str, contents: String) -> SourceFile {
+        SourceFile {
+            path: PathBuf::from(path),
+            contents,
+            tokens: 0,
+            line_range: None,
+        }
+    }
+
+    /// An `impl` block of `count` methods of a few lines each.
+    fn one_impl(count: usize) -> String {
+        let mut source = "struct S;\n\nimpl S {\n".to_string();
+        for index in 0..count {
+            let _ = write!(source, "    /// Method {index}.\n    fn method_{index}(&self) -> u32 {{\n        let x = {index};\n        x + 1\n    }}\n\n");
+        }
+        source.push_str("}\n");
+        source
+    }
+
+    #[test]
+    fn rust_boundaries_include_items_inside_impls_traits_and_modules() {
+        let source = "impl S {\n    fn a() {}\n    fn b() {}\n}\ntrait T {\n    fn c();\n}\nmod m {\n    fn d() {}\n}\n";
+        let lines: Vec<usize> = boundaries(Path::new("a.rs"), source)
+            .into_iter()
+            .map(|offset| source[..offset].lines().count() + 1)
+            .collect();
+        assert_eq!(lines, [1, 2, 3, 5, 6, 8, 9]);
+    }
+
+    #[test]
+    fn a_large_impl_is_split_between_its_methods() {
+        let file = file("./src/s.rs", one_impl(12));
+        let parts = split_file(
+            &file,
+            300,
+            ChunkLimit::Bytes(300),
+            OutputFormat::Plain,
+            &Tokenizer::default(),
+        );
+        assert!(parts.len() > 2);
+        for part in &parts[1..] {
+            let first = part.contents.lines().next().unwrap().trim_start();
+            assert!(
+                first.starts_with("/// Method") || first == "}",
+                "part starts with {first:?}"
+            );
+        }
+        assert_eq!(
+            parts
+                .iter()
+                .map(|part| part.contents.as_str())
+                .collect::<String>(),
+            file.contents
+        );
+    }
+
+    #[test]
+    fn parts_are_labeled_with_consecutive_line_ranges() {
+        let file = file("./src/s.rs", one_impl(12));
+        let parts = split_file(
+            &file,
+            300,
+            ChunkLimit::Bytes(300),
+            OutputFormat::Plain,
+            &Tokenizer::default(),
+        );
+        let mut next = 1;
+        for part in &parts {
+            let (first, last) = part.line_range.unwrap();
+            assert_eq!(first, next);
+            next = last + 1;
+        }
+        assert_eq!(next, file.contents.lines().count() + 1);
+    }
+
+    #[test]
+    fn other_files_break_at_paragraphs() {
+        assert_eq!(
+            boundaries(Path::new("notes.md"), "one\ntwo\n\nthree\n\n\nfour\n"),
+            [9, 17]
+        );
+    }
+}
@@ -0,0 +1,162 @@
+use crate::{FileLimit, IgnoreMode, LimitRule, OutputFormat, OverBudget, Section};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Project configuration file, looked up in the working directory.
+pub const CONFIG_FILE: &str = "llm-prompt.toml";
+
+/// The profile used when none is named.
+pub const DEFAULT_PROFILE: &str = "default";
+
+/// The contents of `llm-prompt.toml`: named profiles under `[profiles.<name>]`.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct Config {
+    #[serde(default)]
+    pub profiles: BTreeMap<String, Profile>,
+}
+
+/// Everything a prompt can be configured with. Unset values fall back to the defaults
+/// of the command line.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct Profile {
+    /// Where the prompt is written, relative to the working directory.
+    pub output: Option<PathBuf>,
+    pub format: Option<OutputFormat>,
+    pub tokenizer: Option<String>,
+    pub max_tokens: Option<usize>,
+    pub over_budget: Option<OverBudget>,
+    pub gitignore: Option<IgnoreMode>,
+    pub max_depth: Option<usize>,
+    /// Also read hidden files and directories.
+    pub hidden: Option<bool>,
+    /// The most any file may hold, such as `64kb`, `2000 lines` or `8000 tokens`.
+    pub max_file_size: Option<FileLimit>,
+    /// Limits for the files matching a glob, as `glob=limit` or `glob=none`; the last
+    /// matching one wins over `max-file-size`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub file_limits: Vec<LimitRule>,
+    /// A template laying out the prompt, relative to the working directory.
+    pub template: Option<PathBuf>,
+    /// The sections of the prompt, in order.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub sections: Vec<SectionConfig>,
+}
+
+/// One section of a profile, as `[[profiles.<name>.sections]]`.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct SectionConfig {
+    pub name: String,
+    #[serde(default)]
+    pub include: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub exclude: Vec<String>,
+    pub context: Option<String>,
+}
+
+impl Config {
+    /// Reads `llm-prompt.toml` from `dir`, if there is one.
+    ///
+    /// # Errors
+    /// Returns an error if the file cannot be read or is not a valid configuration.
+    pub fn load(dir: &Path) -> Result<Option<Self>, String> {
+        let path = dir.join(CONFIG_FILE);
+        if !path.is_file() {
+            return Ok(None);
+        }
+        let text = fs::read_to_string(&path).map_err(|e| format!("{}: {e}", path.display()))?;
+        toml::from_str(&text)
+            .map(Some)
+            .map_err(|e| format!("{}: {e}", path.display()))
+    }
+
+    /// The profile called `name`, or the `default` profile (if any) when `name` is `None`.
+    ///
+    /// # Errors
+    /// Returns an error if a profile was named but is not defined.
+    pub fn profile(&self, name: Option<&str>) -> Result<Profile, String> {
+        let Some(name) = name else {
+            return Ok(self
+                .profiles
+                .get(DEFAULT_PROFILE)
+                .cloned()
+                .unwrap_or_default());
+        };
+        self.profiles.get(name).cloned().ok_or_else(|| {
+            let known: Vec<&str> = self.profiles.keys().map(String::as_str).collect();
+            format!(
+                "Unknown profile [{name}], {CONFIG_FILE} defines: {}",
+                known.join(", ")
+            )
+        })
+    }
+}
+
+impl Profile {
+    /// Layers `overrides` on top of this profile. Set values replace the profile's, and
+    /// file limit rules are added after the profile's so that they win. Sections are
+    /// matched by name: a non-empty glob list or a context replaces the one of the
+    /// section with the same name, and unknown sections are appended.
+    #[must_use]
+    pub fn merge(mut self, overrides: Self) -> Self {
+        self.output = overrides.output.or(self.output);
+        self.format = overrides.format.or(self.format);
+        self.tokenizer = overrides.tokenizer.or(self.tokenizer);
+        self.max_tokens = overrides.max_tokens.or(self.max_tokens);
+        self.over_budget = overrides.over_budget.or(self.over_budget);
+        self.gitignore = overrides.gitignore.or(self.gitignore);
+        self.max_depth = overrides.max_depth.or(self.max_depth);
+        self.hidden = overrides.hidden.or(self.hidden);
+        self.max_file_size = overrides.max_file_size.or(self.max_file_size);
+        self.file_limits.extend(overrides.file_limits);
+        self.template = overrides.template.or(self.template);
+        for section in overrides.sections {
+            match self
+                .sections
+                .iter_mut()
+                .find(|existing| existing.name == section.name)
+            {
+                Some(existing) => {
+                    if !section.include.is_empty() {
+                        existing.include = section.include;
+                    }
+                    if !section.exclude.is_empty() {
+                        existing.exclude = section.exclude;
+                    }
+                    if section.context.is_some() {
+                        existing.context = section.context;
+                    }
+                }
+                None => self.sections.push(section),
+            }
+        }
+        self
+    }
+
+    /// The profile as it would be written in `llm-prompt.toml`.
+    ///
+    /// # Errors
+    /// Returns an error if the profile cannot be represented as TOML.
+    pub fn to_toml(&self) -> Result<String, String> {
+        toml::to_string_pretty(self).map_err(|e| e.to_string())
+    }
+}
+
+impl SectionConfig {
+    /// The section in the form the prompt builder takes; without a context, the files
+    /// are not followed by any paragraph.
+    #[must_use]
+    pub fn section(&self) -> Section<'_> {
+        Section {
+            name: &self.name,
+            include: &self.include,
+            exclude: &self.exclude,
+            context: self.context.as_deref(),
+            default_context: "",
+        }
+    }
+}
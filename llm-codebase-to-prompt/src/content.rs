@@ -0,0 +1,92 @@
+use content_inspector::ContentType;
+use encoding_rs::{Encoding, UTF_16BE, UTF_16LE};
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// What was found in a file once its bytes were sniffed.
+pub enum Content {
+    /// Text, decoded to UTF-8. `encoding` names the original encoding when the file
+    /// had to be transcoded.
+    Text {
+        text: String,
+        encoding: Option<&'static str>,
+    },
+    /// Anything that does not look like text.
+    Binary { size: u64, mime: String },
+}
+
+impl Content {
+    /// The line written into the prompt in place of a binary file.
+    #[must_use]
+    pub fn placeholder(size: u64, mime: &str) -> String {
+        format!("[binary file omitted: {size} bytes, {mime}]")
+    }
+}
+
+/// Reads a file, decoding any text encoding to UTF-8 and detecting binary content.
+///
+/// # Errors
+/// Returns any error from reading the file.
+pub fn read(path: &Path) -> io::Result<Content> {
+    let bytes = fs::read(path)?;
+    Ok(sniff(path, &bytes))
+}
+
+fn sniff(path: &Path, bytes: &[u8]) -> Content {
+    let text = match content_inspector::inspect(bytes) {
+        ContentType::BINARY => None,
+        ContentType::UTF_8 => Some(
+            std::str::from_utf8(bytes)
+                .map_or_else(|_| guess_and_decode(bytes), |text| (text.to_string(), None)),
+        ),
+        ContentType::UTF_8_BOM => Some((String::from_utf8_lossy(&bytes[3..]).into_owned(), None)),
+        ContentType::UTF_16LE => Some(decode_with(UTF_16LE, bytes)),
+        ContentType::UTF_16BE => Some(decode_with(UTF_16BE, bytes)),
+        ContentType::UTF_32LE => {
+            decode_utf32(&bytes[4..], u32::from_le_bytes).map(|text| (text, Some("UTF-32LE")))
+        }
+        ContentType::UTF_32BE => {
+            decode_utf32(&bytes[4..], u32::from_be_bytes).map(|text| (text, Some("UTF-32BE")))
+        }
+    };
+
+    match text {
+        Some((text, encoding)) => Content::Text { text, encoding },
+        None => Content::Binary {
+            size: bytes.len() as u64,
+            mime: guess_mime(path, bytes),
+        },
+    }
+}
+
+/// Decodes bytes that are text but not valid UTF-8, such as Windows-1252 or Latin-1.
+fn guess_and_decode(bytes: &[u8]) -> (String, Option<&'static str>) {
+    let mut detector = chardetng::EncodingDetector::new();
+    detector.feed(bytes, true);
+    decode_with(detector.guess(None, false), bytes)
+}
+
+fn decode_with(encoding: &'static Encoding, bytes: &[u8]) -> (String, Option<&'static str>) {
+    let (text, actual, _) = encoding.decode(bytes);
+    (text.into_owned(), Some(actual.name()))
+}
+
+fn decode_utf32(bytes: &[u8], from_bytes: fn([u8; 4]) -> u32) -> Option<String> {
+    bytes
+        .chunks_exact(4)
+        .map(|chunk| char::from_u32(from_bytes([chunk[0], chunk[1], chunk[2], chunk[3]])))
+        .collect()
+}
+
+/// Guesses a MIME type from magic bytes, falling back to the file extension.
+fn guess_mime(path: &Path, bytes: &[u8]) -> String {
+    infer::get(bytes).map_or_else(
+        || {
+            mime_guess::from_path(path)
+                .first_or_octet_stream()
+                .to_string()
+        },
+        |kind| kind.mime_type().to_string(),
+    )
+}
@@ -0,0 +1,413 @@
+use serde_json::Value;
+use std::fmt::{self, Write as _};
+use std::path::Path;
+
+/// How notebooks and data files are summarized.
+#[derive(Clone, Copy, Debug)]
+pub struct DataOptions {
+    /// How many rows of a table, or elements of a JSON array, are shown.
+    pub sample: usize,
+    /// Keep the text outputs of notebook cells.
+    pub notebook_outputs: bool,
+    /// Data files smaller than this many bytes are kept whole, so configuration such
+    /// as `package.json` reaches the prompt as is. Notebooks are rendered at any size.
+    pub min_bytes: usize,
+}
+
+impl Default for DataOptions {
+    fn default() -> Self {
+        Self {
+            sample: 5,
+            notebook_outputs: false,
+            min_bytes: 32 * 1024,
+        }
+    }
+}
+
+/// Renders a notebook as its cells, and a data file as its schema and a sample.
+///
+/// Data files are CSV, TSV, JSON Lines and JSON. `None` for any other file, for data
+/// files under `min_bytes`, and for JSON without an array longer than the sample.
+///
+/// # Errors
+/// Returns an error if the file does not parse as the format its extension names.
+pub fn extract(path: &Path, text: &str, options: &DataOptions) -> Result<Option<String>, String> {
+    let extension = path
+        .extension()
+        .map(|extension| extension.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    if extension != "ipynb" && text.len() < options.min_bytes {
+        return Ok(None);
+    }
+    match extension.as_str() {
+        "ipynb" => notebook(text, options).map(Some),
+        "csv" => table(text, b',', options).map(Some),
+        "tsv" => table(text, b'\t', options).map(Some),
+        "jsonl" | "ndjson" => json_lines(text, options).map(Some),
+        "json" => json(text, options),
+        _ => Ok(None),
+    }
+}
+
+/// The cells in order, in the `# %%` percent format: code as is, markdown and outputs
+/// as comments. Images and other rich outputs are dropped.
+fn notebook(text: &str, options: &DataOptions) -> Result<String, String> {
+    let notebook: Value = serde_json::from_str(text).map_err(|e| e.to_string())?;
+    let cells = notebook["cells"]
+        .as_array()
+        .ok_or("no cells in the notebook")?;
+    let metadata = &notebook["metadata"];
+    let language = metadata["kernelspec"]["language"]
+        .as_str()
+        .or_else(|| metadata["language_info"]["name"].as_str());
+
+    let mut rendered = String::new();
+    if let Some(language) = language {
+        let _ = writeln!(rendered, "# Notebook ({language}), {} cells", cells.len());
+    }
+    for cell in cells {
+        let source = joined(&cell["source"]);
+        match cell["cell_type"].as_str().unwrap_or_default() {
+            "code" => {
+                match cell["execution_count"].as_u64() {
+                    Some(count) => {
+                        let _ = writeln!(rendered, "\n# %% In [{count}]");
+                    }
+                    None => rendered.push_str("\n# %%\n"),
+                }
+                push_lines(&mut rendered, &source, "");
+                if options.notebook_outputs {
+                    for output in cell["outputs"].as_array().into_iter().flatten() {
+                        push_output(&mut rendered, output);
+                    }
+                }
+            }
+            kind => {
+                let _ = writeln!(rendered, "\n# %% [{kind}]");
+                push_lines(&mut rendered, &source, "# ");
+            }
+        }
+    }
+    Ok(rendered)
+}
+
+fn push_output(rendered: &mut String, output: &Value) {
+    match output["output_type"].as_str().unwrap_or_default() {
+        "stream" => {
+            rendered.push_str("# Out:\n");
+            push_lines(rendered, &joined(&output["text"]), "# ");
+        }
+        "error" => {
+            let name = output["ename"].as_str().unwrap_or("Error");
+            let _ = writeln!(
+                rendered,
+                "# Error: {name}: {}",
+                output["evalue"].as_str().unwrap_or_default()
+            );
+        }
+        _ => {
+            let data = &output["data"];
+            if let Some(text) = data.get("text/plain") {
+                rendered.push_str("# Out:\n");
+                push_lines(rendered, &joined(text), "# ");
+            } else if let Some(data) = data.as_object() {
+                let kinds: Vec<&str> = data.keys().map(String::as_str).collect();
+                let _ = writeln!(rendered, "# [{} output omitted]", kinds.join(", "));
+            }
+        }
+    }
+}
+
+/// A notebook string, stored either whole or as a list of lines.
+fn joined(value: &Value) -> String {
+    match value {
+        Value::Array(lines) => lines.iter().filter_map(Value::as_str).collect(),
+        Value::String(text) => text.clone(),
+        _ => String::new(),
+    }
+}
+
+fn push_lines(rendered: &mut String, text: &str, prefix: &str) {
+    for line in text.lines() {
+        let _ = writeln!(rendered, "{}", format!("{prefix}{line}").trim_end());
+    }
+}
+
+/// The columns with their types, the row count, and the first rows.
+fn table(text: &str, delimiter: u8, options: &DataOptions) -> Result<String, String> {
+    let mut reader = csv::ReaderBuilder::new()
+        .delimiter(delimiter)
+        .flexible(true)
+        .from_reader(text.as_bytes());
+    let headers = reader.headers().map_err(|e| e.to_string())?.clone();
+    let mut columns = vec![Shape::Unknown; headers.len()];
+    let mut sample = Vec::new();
+    let mut rows = 0;
+    for record in reader.records() {
+        let record = record.map_err(|e| e.to_string())?;
+        for (column, field) in columns.iter_mut().zip(record.iter()) {
+            *column = std::mem::replace(column, Shape::Unknown).merge(Shape::of_field(field));
+        }
+        if sample.len() < options.sample {
+            sample.push(record);
+        }
+        rows += 1;
+    }
+
+    let mut rendered = format!("{rows} rows, {} columns:\n", headers.len());
+    for (name, shape) in headers.iter().zip(&columns) {
+        let _ = writeln!(rendered, "  {name}: {shape}");
+    }
+    let _ = writeln!(rendered, "\nFirst {} rows:", sample.len());
+    let mut writer = csv::WriterBuilder::new()
+        .delimiter(delimiter)
+        .flexible(true)
+        .from_writer(Vec::new());
+    for record in std::iter::once(&headers).chain(&sample) {
+        writer.write_record(record).map_err(|e| e.to_string())?;
+    }
+    let written = writer.into_inner().map_err(|e| e.to_string())?;
+    rendered.push_str(&String::from_utf8_lossy(&written));
+    Ok(rendered)
+}
+
+/// One JSON value per line: their shared schema, the line count and the first lines.
+fn json_lines(text: &str, options: &DataOptions) -> Result<String, String> {
+    let mut shape = Shape::Unknown;
+    let mut sample = Vec::new();
+    let mut count = 0;
+    for (number, line) in text
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+    {
+        let value: Value =
+            serde_json::from_str(line).map_err(|e| format!("line {}: {e}", number + 1))?;
+        shape = shape.merge(Shape::of(&value));
+        if sample.len() < options.sample {
+            sample.push(line);
+        }
+        count += 1;
+    }
+    Ok(format!(
+        "{count} records, each:\n  {shape}\n\nFirst {} records:\n{}\n",
+        sample.len(),
+        sample.join("\n")
+    ))
+}
+
+/// The schema of the document and the document with every array cut to the sample.
+fn json(text: &str, options: &DataOptions) -> Result<Option<String>, String> {
+    let value: Value = serde_json::from_str(text).map_err(|e| e.to_string())?;
+    if !has_long_array(&value, options.sample) {
+        return Ok(None);
+    }
+    let sample =
+        serde_json::to_string_pretty(&cut(&value, options.sample)).map_err(|e| e.to_string())?;
+    Ok(Some(format!(
+        "Schema:\n  {}\n\nSample, with arrays cut to {} elements:\n{sample}\n",
+        Shape::of(&value),
+        options.sample
+    )))
+}
+
+fn has_long_array(value: &Value, limit: usize) -> bool {
+    match value {
+        Value::Array(items) => {
+            items.len() > limit || items.iter().any(|item| has_long_array(item, limit))
+        }
+        Value::Object(fields) => fields.values().any(|field| has_long_array(field, limit)),
+        _ => false,
+    }
+}
+
+/// `value` with each array holding its first `limit` elements, then a string counting
+/// the rest.
+fn cut(value: &Value, limit: usize) -> Value {
+    match value {
+        Value::Array(items) => {
+            let mut kept: Vec<Value> = items
+                .iter()
+                .take(limit)
+                .map(|item| cut(item, limit))
+                .collect();
+            if items.len() > limit {
+                kept.push(Value::String(format!("... {} more", items.len() - limit)));
+            }
+            Value::Array(kept)
+        }
+        Value::Object(fields) => Value::Object(
+            fields
+                .iter()
+                .map(|(name, field)| (name.clone(), cut(field, limit)))
+                .collect(),
+        ),
+        value => value.clone(),
+    }
+}
+
+/// The inferred type of a value, or of every value seen in one place.
+#[derive(Clone, Debug, PartialEq)]
+enum Shape {
+    /// Nothing seen yet, such as the elements of an empty array.
+    Unknown,
+    Null,
+    Boolean,
+    Integer,
+    Float,
+    String,
+    Array(Box<Self>),
+    Object(Vec<Field>),
+    /// Values of several kinds; never nested and never holding `Unknown`.
+    Union(Vec<Self>),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct Field {
+    name: String,
+    shape: Shape,
+    /// Missing from some of the objects.
+    optional: bool,
+}
+
+impl Shape {
+    fn of(value: &Value) -> Self {
+        match value {
+            Value::Null => Self::Null,
+            Value::Bool(_) => Self::Boolean,
+            Value::Number(number) if number.is_f64() => Self::Float,
+            Value::Number(_) => Self::Integer,
+            Value::String(_) => Self::String,
+            Value::Array(items) => Self::Array(Box::new(
+                items.iter().map(Self::of).fold(Self::Unknown, Self::merge),
+            )),
+            Value::Object(fields) => Self::Object(
+                fields
+                    .iter()
+                    .map(|(name, field)| Field {
+                        name: name.clone(),
+                        shape: Self::of(field),
+                        optional: false,
+                    })
+                    .collect(),
+            ),
+        }
+    }
+
+    /// The type of a table cell; empty cells are null.
+    fn of_field(field: &str) -> Self {
+        let field = field.trim();
+        if field.is_empty() {
+            Self::Null
+        } else if field.parse::<i64>().is_ok() {
+            Self::Integer
+        } else if field.parse::<f64>().is_ok() {
+            Self::Float
+        } else if field.eq_ignore_ascii_case("true") || field.eq_ignore_ascii_case("false") {
+            Self::Boolean
+        } else {
+            Self::String
+        }
+    }
+
+    /// The type covering both `self` and `other`.
+    fn merge(self, other: Self) -> Self {
+        let mut variants = self.variants();
+        for variant in other.variants() {
+            match variants
+                .iter_mut()
+                .find(|existing| existing.kind() == variant.kind())
+            {
+                Some(existing) => {
+                    *existing = std::mem::replace(existing, Self::Unknown).merge_kind(variant);
+                }
+                None => variants.push(variant),
+            }
+        }
+        match variants.len() {
+            0 => Self::Unknown,
+            1 => variants.remove(0),
+            _ => Self::Union(variants),
+        }
+    }
+
+    fn variants(self) -> Vec<Self> {
+        match self {
+            Self::Unknown => Vec::new(),
+            Self::Union(variants) => variants,
+            shape => vec![shape],
+        }
+    }
+
+    /// Which variants of a union are merged with each other rather than listed apart.
+    const fn kind(&self) -> u8 {
+        match self {
+            Self::Unknown => 0,
+            Self::Null => 1,
+            Self::Boolean => 2,
+            Self::Integer | Self::Float => 3,
+            Self::String => 4,
+            Self::Array(_) => 5,
+            Self::Object(_) => 6,
+            Self::Union(_) => 7,
+        }
+    }
+
+    /// Merges two shapes of the same kind.
+    fn merge_kind(self, other: Self) -> Self {
+        match (self, other) {
+            (Self::Array(a), Self::Array(b)) => Self::Array(Box::new(a.merge(*b))),
+            (Self::Object(mut fields), Self::Object(others)) => {
+                for field in &mut fields {
+                    if !others.iter().any(|other| other.name == field.name) {
+                        field.optional = true;
+                    }
+                }
+                for other in others {
+                    match fields.iter_mut().find(|field| field.name == other.name) {
+                        Some(field) => {
+                            field.shape = std::mem::replace(&mut field.shape, Self::Unknown)
+                                .merge(other.shape);
+                            field.optional |= other.optional;
+                        }
+                        None => fields.push(Field {
+                            optional: true,
+                            ..other
+                        }),
+                    }
+                }
+                Self::Object(fields)
+            }
+            (Self::Integer, Self::Float) | (Self::Float, Self::Integer) => Self::Float,
+            (shape, _) => shape,
+        }
+    }
+}
+
+impl fmt::Display for Shape {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Unknown => write!(f, "unknown"),
+            Self::Null => write!(f, "null"),
+            Self::Boolean => write!(f, "boolean"),
+            Self::Integer => write!(f, "integer"),
+            Self::Float => write!(f, "float"),
+            Self::String => write!(f, "string"),
+            Self::Array(items) => write!(f, "[{items}]"),
+            Self::Object(fields) if fields.is_empty() => write!(f, "{{}}"),
+            Self::Object(fields) => {
+                write!(f, "{{ ")?;
+                for (index, field) in fields.iter().enumerate() {
+                    let separator = if index == 0 { "" } else { ", " };
+                    let optional = if field.optional { "?" } else { "" };
+                    write!(f, "{separator}{}{optional}: {}", field.name, field.shape)?;
+                }
+                write!(f, " }}")
+            }
+            Self::Union(variants) => {
+                let variants: Vec<String> = variants.iter().map(ToString::to_string).collect();
+                write!(f, "{}", variants.join(" | "))
+            }
+        }
+    }
+}
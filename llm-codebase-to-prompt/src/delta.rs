@@ -0,0 +1,346 @@
+use crate::{OutputFormat, SourceFile, Tokenizer};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt::Write as _;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+/// Emit only what changed since the last prompt written with the same manifest.
+#[derive(Clone, Debug)]
+pub struct DeltaOptions {
+    /// Where the manifest of the last prompt is kept. Until it exists, the whole prompt
+    /// is emitted.
+    pub manifest: PathBuf,
+    /// Show modified files as unified diffs against the version sent before.
+    pub diffs: bool,
+}
+
+/// The files a prompt held, by content hash, so the next prompt can leave out those
+/// that did not change.
+///
+/// For diffs, the contents are kept as well, as written after every transformation,
+/// to diff the next version against.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Manifest {
+    files: BTreeMap<PathBuf, Entry>,
+}
+
+/// One file of a manifest.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub(crate) struct Entry {
+    sha256: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    contents: Option<String>,
+}
+
+impl Entry {
+    /// The entry of a file reading `contents`, keeping them only for `diffs`.
+    pub(crate) fn new(contents: &str, diffs: bool) -> Self {
+        Self {
+            sha256: hash(contents),
+            contents: diffs.then(|| contents.to_string()),
+        }
+    }
+}
+
+impl Manifest {
+    /// Reads the manifest at `path`, or `None` if no prompt was written with it yet.
+    ///
+    /// # Errors
+    /// Returns an error if the file cannot be read or is not a manifest.
+    pub fn load(path: &Path) -> Result<Option<Self>, String> {
+        match fs::read_to_string(path) {
+            Ok(text) => serde_json::from_str(&text)
+                .map(Some)
+                .map_err(|e| format!("{}: {e}", path.display())),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(format!("{}: {e}", path.display())),
+        }
+    }
+
+    /// Writes the manifest to `path`.
+    ///
+    /// # Errors
+    /// Returns an error if the file cannot be written.
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        let json = serde_json::to_string(self).map_err(|e| e.to_string())?;
+        fs::write(path, json).map_err(|e| format!("{}: {e}", path.display()))
+    }
+
+    /// How many files the prompt held.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.files.len()
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+
+    /// The manifest of a prompt holding `sections`, whose files read as `current`
+    /// before any of them were left out, and were compared with `previous` into
+    /// `delta`. Files left out as unchanged keep their entry, while those dropped or
+    /// truncated for the budget are not recorded, so the next update sends them whole.
+    pub(crate) fn record(
+        current: &BTreeMap<PathBuf, Entry>,
+        sections: &[Vec<SourceFile>],
+        previous: Option<&Self>,
+        delta: Option<&Delta>,
+    ) -> Self {
+        let mut files = BTreeMap::new();
+        for file in sections.iter().flatten() {
+            let path = original_path(&file.path, current);
+            let Some(entry) = current.get(&path) else {
+                continue;
+            };
+            let sent = if path == file.path {
+                Some(&entry.sha256)
+            } else {
+                delta.and_then(|delta| delta.patches.get(&file.path))
+            };
+            if sent.is_some_and(|sent| *sent == hash(&file.contents)) {
+                files.insert(path, entry.clone());
+            }
+        }
+        if let Some(previous) = previous {
+            for (path, entry) in current {
+                if previous
+                    .files
+                    .get(path)
+                    .is_some_and(|old| old.sha256 == entry.sha256)
+                {
+                    files.entry(path.clone()).or_insert_with(|| entry.clone());
+                }
+            }
+        }
+        Self { files }
+    }
+}
+
+/// How the files of a prompt differ from those of the previous one.
+#[derive(Default)]
+pub struct Delta {
+    pub added: Vec<PathBuf>,
+    pub modified: Vec<PathBuf>,
+    /// Files of the previous prompt that are gone or no longer selected.
+    pub removed: Vec<PathBuf>,
+    /// The hash of every diff written, by the name of its entry.
+    patches: HashMap<PathBuf, String>,
+}
+
+impl Delta {
+    /// Compares the files of every section with `previous`, drops those that did not
+    /// change, and with `diffs`, replaces each modified file by its unified diff, named
+    /// after the file with `.diff` appended. A file whose earlier contents were not
+    /// kept is written whole.
+    pub(crate) fn apply(
+        sections: &mut [Vec<SourceFile>],
+        previous: &Manifest,
+        diffs: bool,
+        format: OutputFormat,
+        tokenizer: &Tokenizer,
+    ) -> Self {
+        let mut delta = Self::default();
+        let mut seen = HashSet::new();
+        for files in sections.iter_mut() {
+            files.retain_mut(|file| {
+                seen.insert(file.path.clone());
+                let Some(entry) = previous.files.get(&file.path) else {
+                    delta.added.push(file.path.clone());
+                    return true;
+                };
+                if entry.sha256 == hash(&file.contents) {
+                    return false;
+                }
+                delta.modified.push(file.path.clone());
+                if let Some(earlier) = entry.contents.as_deref().filter(|_| diffs) {
+                    let name = file.path.to_string_lossy().into_owned();
+                    let relative = file
+                        .path
+                        .strip_prefix(".")
+                        .unwrap_or(&file.path)
+                        .display()
+                        .to_string();
+                    let mut options = diffy::DiffOptions::new();
+                    options
+                        .set_original_filename(format!("a/{relative}"))
+                        .set_modified_filename(format!("b/{relative}"));
+                    file.contents = options.create_patch(earlier, &file.contents).to_string();
+                    file.path = PathBuf::from(format!("{name}.diff"));
+                    file.tokens = tokenizer.count(&file.render(format));
+                    delta
+                        .patches
+                        .insert(file.path.clone(), hash(&file.contents));
+                }
+                true
+            });
+        }
+        delta.removed = previous
+            .files
+            .keys()
+            .filter(|path| !seen.contains(*path))
+            .cloned()
+            .collect();
+        delta
+    }
+
+    /// The note opening an update, listing what changed.
+    #[must_use]
+    pub fn render(&self) -> String {
+        let mut rendered = "This is an update to the files shared earlier in this conversation. Only the files that \
+                            changed since then follow; every other file is as it was.\n"
+            .to_string();
+        if !self.patches.is_empty() {
+            rendered.push_str(
+                "Modified files are shown as unified diffs against the earlier version.\n",
+            );
+        }
+        if self.added.is_empty() && self.modified.is_empty() && self.removed.is_empty() {
+            rendered.push_str("Nothing changed.\n");
+        }
+        for (status, paths) in [
+            ("added", &self.added),
+            ("modified", &self.modified),
+            ("removed", &self.removed),
+        ] {
+            for path in paths {
+                let _ = writeln!(rendered, "{status} {}", path.display());
+            }
+        }
+        rendered.push('\n');
+        rendered
+    }
+}
+
+/// The path of the file a prompt entry was made from: itself, or the file a `.diff`
+/// entry is the diff of.
+fn original_path(path: &Path, current: &BTreeMap<PathBuf, Entry>) -> PathBuf {
+    if current.contains_key(path) {
+        return path.to_path_buf();
+    }
+    let name = path.to_string_lossy();
+    name.strip_suffix(".diff")
+        .map_or_else(|| path.to_path_buf(), PathBuf::from)
+}
+
+/// The SHA-256 of `contents`, in hex.
+fn hash(contents: &str) -> String {
+    Sha256::digest(contents.as_bytes())
+        .iter()
+        .fold(String::new(), |mut hex, byte| {
+            let _ = write!(hex, "{byte:02x}");
+            hex
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn file(path: &str, contents: &str) -> SourceFile {
+        SourceFile {
+            path: PathBuf::from(path),
+            contents: contents.to_string(),
+            tokens: 0,
+            line_range: None,
+        }
+    }
+
+    fn current(files: &[(&str, &str)], diffs: bool) -> BTreeMap<PathBuf, Entry> {
+        files
+            .iter()
+            .map(|(path, contents)| (PathBuf::from(path), Entry::new(contents, diffs)))
+            .collect()
+    }
+
+    #[test]
+    fn record_leaves_out_files_cut_for_the_budget() {
+        let current = current(
+            &[
+                ("./a.rs", "fn a() {}\nfn b() {}\n"),
+                ("./b.rs", "fn c() {}\n"),
+            ],
+            false,
+        );
+        let sent = [vec![
+            file("./a.rs", "fn a() {}\n[... truncated ...]"),
+            file("./b.rs", "fn c() {}\n"),
+        ]];
+        let manifest = Manifest::record(&current, &sent, None, None);
+        assert_eq!(
+            manifest.files.keys().collect::<Vec<_>>(),
+            [Path::new("./b.rs")]
+        );
+    }
+
+    #[test]
+    fn unchanged_files_are_dropped_and_kept_in_the_manifest() {
+        let previous = Manifest {
+            files: current(
+                &[
+                    ("./a.rs", "one\n"),
+                    ("./b.rs", "two\n"),
+                    ("./c.rs", "gone\n"),
+                ],
+                false,
+            ),
+        };
+        let current = current(
+            &[
+                ("./a.rs", "one\n"),
+                ("./b.rs", "three\n"),
+                ("./d.rs", "new\n"),
+            ],
+            false,
+        );
+        let mut sections = vec![vec![
+            file("./a.rs", "one\n"),
+            file("./b.rs", "three\n"),
+            file("./d.rs", "new\n"),
+        ]];
+        let delta = Delta::apply(
+            &mut sections,
+            &previous,
+            false,
+            OutputFormat::Plain,
+            &Tokenizer::default(),
+        );
+        assert_eq!(delta.added, [Path::new("./d.rs")]);
+        assert_eq!(delta.modified, [Path::new("./b.rs")]);
+        assert_eq!(delta.removed, [Path::new("./c.rs")]);
+        assert_eq!(sections[0].len(), 2);
+
+        let manifest = Manifest::record(&current, &sections, Some(&previous), Some(&delta));
+        assert_eq!(manifest.len(), 3);
+        assert!(!manifest.files.contains_key(Path::new("./c.rs")));
+    }
+
+    #[test]
+    fn diffs_are_recorded_under_their_file_unless_cut() {
+        let previous = Manifest {
+            files: current(&[("./a.rs", "one\ntwo\n")], true),
+        };
+        let current = current(&[("./a.rs", "one\nthree\n")], true);
+        let mut sections = vec![vec![file("./a.rs", "one\nthree\n")]];
+        let delta = Delta::apply(
+            &mut sections,
+            &previous,
+            true,
+            OutputFormat::Plain,
+            &Tokenizer::default(),
+        );
+        assert_eq!(sections[0][0].path, Path::new("./a.rs.diff"));
+        assert!(sections[0][0].contents.contains("+three"));
+        assert!(
+            Manifest::record(&current, &sections, Some(&previous), Some(&delta))
+                .files
+                .contains_key(Path::new("./a.rs"))
+        );
+
+        sections[0][0].contents.truncate(10);
+        assert!(Manifest::record(&current, &sections, Some(&previous), Some(&delta)).is_empty());
+    }
+}
@@ -0,0 +1,65 @@
+use crate::SecretFinding;
+use std::{fmt, io};
+
+/// Why a prompt could not be generated.
+#[derive(Debug)]
+pub enum Error {
+    /// An include, exclude or outline glob is not valid.
+    Pattern(String),
+    /// The directory walk failed.
+    Walk(String),
+    /// The git repository or a revision could not be read.
+    Git(String),
+    /// The prompt is over `max_tokens` and the policy is to fail; lists the largest files.
+    OverBudget(String),
+    /// Secrets were found and the policy is to fail rather than redact them.
+    Secrets(Vec<SecretFinding>),
+    /// The entry file to follow modules from is not a Rust file under the root.
+    Entry(String),
+    /// The prompt template is invalid or failed to render.
+    Template(String),
+    /// The manifest of the last prompt could not be read.
+    Manifest(String),
+    /// The file watcher failed or stopped.
+    Watch(String),
+    /// Writing the prompt failed.
+    Io(io::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Pattern(e) => write!(f, "Invalid pattern: {e}"),
+            Self::Walk(e) => write!(f, "Error walking directory: {e}"),
+            Self::Git(e) => write!(f, "Git error: {e}"),
+            Self::OverBudget(e) => write!(f, "{e}"),
+            Self::Secrets(findings) => {
+                write!(f, "Found {} secrets:", findings.len())?;
+                for finding in findings {
+                    write!(f, "\n  {finding}")?;
+                }
+                Ok(())
+            }
+            Self::Entry(e) => write!(f, "Invalid entry: {e}"),
+            Self::Template(e) => write!(f, "Template error: {e}"),
+            Self::Manifest(e) => write!(f, "Error reading manifest: {e}"),
+            Self::Watch(e) => write!(f, "Watch error: {e}"),
+            Self::Io(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Self::Io(e)
+    }
+}
@@ -0,0 +1,172 @@
+use crate::{language, SourceFile};
+use std::io::{self, Write};
+use std::path::Path;
+
+/// How files are laid out in the generated prompt.
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    Default,
+    PartialEq,
+    Eq,
+    clap::ValueEnum,
+    serde::Serialize,
+    serde::Deserialize,
+)]
+#[serde(rename_all = "kebab-case")]
+pub enum OutputFormat {
+    /// A `// path` comment line followed by the file contents.
+    #[default]
+    Plain,
+    /// Each file wrapped in a `<file path="...">` tag, as recommended by Anthropic.
+    Xml,
+    /// A Markdown fenced code block per file, tagged with the detected language.
+    Markdown,
+    /// A JSON array of `{path, language, content}` objects per section.
+    Json,
+}
+
+impl OutputFormat {
+    /// Renders a single file the way it appears in the prompt.
+    #[must_use]
+    pub fn render_file(self, path: &Path, contents: &str) -> String {
+        self.render_named(&path.to_string_lossy(), language::detect(path), contents)
+    }
+
+    /// Renders file contents under a display name, such as a path with a line range.
+    #[must_use]
+    pub fn render_named(self, name: &str, language: Option<&str>, contents: &str) -> String {
+        match self {
+            Self::Plain => format!("// {name}\n{contents}\n"),
+            Self::Xml => format!(
+                "<file path=\"{}\">\n{}{}</file>\n",
+                escape_attribute(name),
+                contents,
+                newline_if_missing(contents)
+            ),
+            Self::Markdown => {
+                let fence = fence_for(contents);
+                let language = language.unwrap_or_default();
+                format!(
+                    "{name}\n{fence}{language}\n{contents}{}{fence}\n",
+                    newline_if_missing(contents)
+                )
+            }
+            Self::Json => {
+                let object = serde_json::json!({
+                    "path": name,
+                    "language": language,
+                    "content": contents,
+                });
+                let pretty = serde_json::to_string_pretty(&object).unwrap_or_default();
+                pretty
+                    .lines()
+                    .map(|line| format!("  {line}"))
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            }
+        }
+    }
+
+    /// Renders the project tree shown at the top of the prompt.
+    #[must_use]
+    pub fn render_tree(self, tree: &str) -> String {
+        match self {
+            Self::Plain => format!("Project structure:\n{tree}\n"),
+            Self::Xml => format!("<project_tree>\n{tree}</project_tree>\n\n"),
+            Self::Markdown => format!("Project structure:\n```text\n{tree}```\n\n"),
+            Self::Json => {
+                let object = serde_json::json!({ "project_tree": tree });
+                format!(
+                    "{}\n\n",
+                    serde_json::to_string_pretty(&object).unwrap_or_default()
+                )
+            }
+        }
+    }
+
+    /// Renders the statistics shown at the top of the prompt.
+    #[must_use]
+    pub fn render_stats(self, stats: &str) -> String {
+        match self {
+            Self::Plain => format!("Prompt statistics:\n{stats}\n"),
+            Self::Xml => format!("<prompt_stats>\n{stats}</prompt_stats>\n\n"),
+            Self::Markdown => format!("Prompt statistics:\n```text\n{stats}```\n\n"),
+            Self::Json => {
+                let object = serde_json::json!({ "prompt_stats": stats });
+                format!(
+                    "{}\n\n",
+                    serde_json::to_string_pretty(&object).unwrap_or_default()
+                )
+            }
+        }
+    }
+
+    /// Writes the files of one section, in order.
+    ///
+    /// # Errors
+    /// Returns any error from writing to `output`.
+    pub fn write_files(self, output: &mut impl Write, files: &[SourceFile]) -> io::Result<()> {
+        for (index, file) in files.iter().enumerate() {
+            self.write_file(output, file, index)?;
+        }
+        self.finish_files(output, files.len())
+    }
+
+    /// Writes the file at `index` in its section; [`Self::finish_files`] closes the
+    /// section once every file is written.
+    ///
+    /// # Errors
+    /// Returns any error from writing to `output`.
+    pub fn write_file(
+        self,
+        output: &mut impl Write,
+        file: &SourceFile,
+        index: usize,
+    ) -> io::Result<()> {
+        if self == Self::Json {
+            let separator = if index == 0 { "[\n" } else { ",\n" };
+            return write!(output, "{separator}{}", file.render(self));
+        }
+        writeln!(output, "{}", file.render(self))
+    }
+
+    /// Closes a section of `count` files.
+    ///
+    /// # Errors
+    /// Returns any error from writing to `output`.
+    pub fn finish_files(self, output: &mut impl Write, count: usize) -> io::Result<()> {
+        match (self, count) {
+            (Self::Json, 0) => writeln!(output, "[]\n"),
+            (Self::Json, _) => writeln!(output, "\n]\n"),
+            _ => Ok(()),
+        }
+    }
+}
+
+const fn newline_if_missing(contents: &str) -> &'static str {
+    if contents.is_empty() || contents.as_bytes()[contents.len() - 1] == b'\n' {
+        ""
+    } else {
+        "\n"
+    }
+}
+
+/// Picks a backtick fence longer than any backtick run inside `contents`.
+fn fence_for(contents: &str) -> String {
+    let longest = contents
+        .split(|c| c != '`')
+        .map(str::len)
+        .max()
+        .unwrap_or(0);
+    "`".repeat(longest.max(2) + 1)
+}
+
+fn escape_attribute(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('"', "&quot;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
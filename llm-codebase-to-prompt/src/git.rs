@@ -0,0 +1,194 @@
+use git2::{Delta, Diff, DiffFindOptions, DiffFormat, DiffOptions, Object, Repository, Tree};
+use std::path::{Path, PathBuf};
+
+/// Where the changed files come from.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ChangeSource {
+    /// `A..B` compares two revisions, `A...B` compares `B` with its merge base with `A`,
+    /// and a single revision is compared with the working tree. An empty side is `HEAD`.
+    Range(String),
+    /// Changes staged in the index, compared with `HEAD`.
+    Staged,
+}
+
+/// Restricts the prompt to the files touched in a git revision range.
+#[derive(Clone, Debug)]
+pub struct GitOptions {
+    pub source: ChangeSource,
+    /// Add the unified diff of the changes.
+    pub diff: bool,
+    /// Add the version of each changed file from before the change.
+    pub original: bool,
+}
+
+/// A file touched by the change, with its path relative to the working directory.
+pub struct ChangedFile {
+    pub path: PathBuf,
+    /// `A`dded, `M`odified, `D`eleted or `R`enamed, as in `git status --short`.
+    pub status: char,
+    /// The file before the change, if asked for and the file is text.
+    pub original: Option<String>,
+}
+
+/// Every file touched by a revision range.
+pub struct ChangeSet {
+    /// The range as given, such as `main..HEAD`, or `the staging area`.
+    pub label: String,
+    /// The short id of the revision the originals were taken from.
+    pub base: String,
+    pub files: Vec<ChangedFile>,
+    /// Unified diff of the whole change, empty unless asked for.
+    pub patch: String,
+}
+
+impl ChangeSet {
+    /// Whether `path`, as produced by walking the working directory, was changed.
+    #[must_use]
+    pub fn contains(&self, path: &Path) -> bool {
+        self.files.iter().any(|file| file.path == path)
+    }
+}
+
+/// Reads the changes from the git repository containing `root`.
+///
+/// # Errors
+/// Returns an error if `root` is not inside a non-bare repository or a revision
+/// cannot be resolved.
+pub fn changes(root: &Path, options: &GitOptions) -> Result<ChangeSet, String> {
+    let repo = Repository::discover(root).map_err(|e| e.message().to_string())?;
+    let workdir = repo
+        .workdir()
+        .ok_or("Cannot list changes in a bare repository")?;
+    let root = root.canonicalize().map_err(|e| e.to_string())?;
+    let workdir = workdir.canonicalize().map_err(|e| e.to_string())?;
+    let prefix = root
+        .strip_prefix(&workdir)
+        .map_err(|e| e.to_string())?
+        .to_path_buf();
+
+    let mut diff_options = DiffOptions::new();
+    diff_options
+        .include_untracked(true)
+        .recurse_untracked_dirs(true);
+    if !prefix.as_os_str().is_empty() {
+        diff_options.pathspec(&prefix);
+    }
+
+    let (mut diff, base, label) = match &options.source {
+        ChangeSource::Staged => {
+            let head = resolve(&repo, "HEAD")?;
+            let diff = repo
+                .diff_tree_to_index(Some(&tree(&head)?), None, Some(&mut diff_options))
+                .map_err(|e| e.message().to_string())?;
+            (diff, head, "the staging area".to_string())
+        }
+        ChangeSource::Range(range) => {
+            let (diff, base) = range_diff(&repo, range, &mut diff_options)?;
+            (diff, base, range.clone())
+        }
+    };
+    diff.find_similar(Some(DiffFindOptions::new().renames(true)))
+        .map_err(|e| e.message().to_string())?;
+
+    let mut files = Vec::new();
+    for delta in diff.deltas() {
+        let status = match delta.status() {
+            Delta::Added | Delta::Untracked => 'A',
+            Delta::Deleted => 'D',
+            Delta::Renamed => 'R',
+            _ => 'M',
+        };
+        let Some(path) = delta.new_file().path().or_else(|| delta.old_file().path()) else {
+            continue;
+        };
+        let path = Path::new(".").join(path.strip_prefix(&prefix).unwrap_or(path));
+
+        let original = if options.original && status != 'A' {
+            repo.find_blob(delta.old_file().id())
+                .ok()
+                .filter(|blob| !blob.is_binary())
+                .map(|blob| String::from_utf8_lossy(blob.content()).into_owned())
+        } else {
+            None
+        };
+        files.push(ChangedFile {
+            path,
+            status,
+            original,
+        });
+    }
+
+    let patch = if options.diff {
+        patch(&diff)?
+    } else {
+        String::new()
+    };
+    let base = base
+        .short_id()
+        .ok()
+        .and_then(|id| id.as_str().map(str::to_string))
+        .unwrap_or_default();
+    Ok(ChangeSet {
+        label,
+        base,
+        files,
+        patch,
+    })
+}
+
+/// Diffs a `A..B`, `A...B` or single revision range, returning the base revision.
+fn range_diff<'r>(
+    repo: &'r Repository,
+    range: &str,
+    diff_options: &mut DiffOptions,
+) -> Result<(Diff<'r>, Object<'r>), String> {
+    let side = |rev: &str| resolve(repo, if rev.is_empty() { "HEAD" } else { rev });
+
+    if let Some((from, to)) = range.split_once("...") {
+        let (from, to) = (side(from)?, side(to)?);
+        let base_id = repo
+            .merge_base(from.id(), to.id())
+            .map_err(|e| e.message().to_string())?;
+        let base = repo
+            .find_object(base_id, None)
+            .map_err(|e| e.message().to_string())?;
+        let diff = repo
+            .diff_tree_to_tree(Some(&tree(&base)?), Some(&tree(&to)?), Some(diff_options))
+            .map_err(|e| e.message().to_string())?;
+        Ok((diff, base))
+    } else if let Some((from, to)) = range.split_once("..") {
+        let (from, to) = (side(from)?, side(to)?);
+        let diff = repo
+            .diff_tree_to_tree(Some(&tree(&from)?), Some(&tree(&to)?), Some(diff_options))
+            .map_err(|e| e.message().to_string())?;
+        Ok((diff, from))
+    } else {
+        let from = side(range)?;
+        let diff = repo
+            .diff_tree_to_workdir_with_index(Some(&tree(&from)?), Some(diff_options))
+            .map_err(|e| e.message().to_string())?;
+        Ok((diff, from))
+    }
+}
+
+fn resolve<'r>(repo: &'r Repository, rev: &str) -> Result<Object<'r>, String> {
+    repo.revparse_single(rev)
+        .map_err(|e| format!("Unknown revision [{rev}]: {}", e.message()))
+}
+
+fn tree<'r>(object: &Object<'r>) -> Result<Tree<'r>, String> {
+    object.peel_to_tree().map_err(|e| e.message().to_string())
+}
+
+fn patch(diff: &Diff) -> Result<String, String> {
+    let mut patch = String::new();
+    diff.print(DiffFormat::Patch, |_, _, line| {
+        if matches!(line.origin(), '+' | '-' | ' ') {
+            patch.push(line.origin());
+        }
+        patch.push_str(&String::from_utf8_lossy(line.content()));
+        true
+    })
+    .map_err(|e| e.message().to_string())?;
+    Ok(patch)
+}
@@ -0,0 +1,428 @@
+use log::warn;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use syn::visit::{self, Visit};
+use syn::{Expr, ItemMod, ItemUse, Lit, Meta, UseTree};
+
+/// Restricts the Rust files of the prompt to the modules reachable from one file.
+#[derive(Clone, Debug)]
+pub struct EntryOptions {
+    /// The starting file, relative to the working directory.
+    pub path: PathBuf,
+    /// How many `mod` or `use` steps to follow from the entry; `None` is unlimited.
+    pub depth: Option<usize>,
+    /// Also follow uses backwards, to the modules that depend on the entry.
+    pub dependents: bool,
+}
+
+/// Every Rust file reachable from the entry, as produced by walking the working
+/// directory.
+///
+/// Modules are found by their file layout under the crate's `src`, so `crate::a::b`
+/// is `src/a/b.rs` or `src/a/b/mod.rs`.
+/// The entry's `mod` declarations are followed down its module tree. A module reached
+/// through a path only brings what that path needs: the file an imported name is
+/// re-exported from, or else everything the module itself uses.
+///
+/// # Errors
+/// Returns an error if the entry is not one of the walked Rust files.
+pub fn reachable(
+    root: &Path,
+    paths: &[PathBuf],
+    options: &EntryOptions,
+) -> Result<HashSet<PathBuf>, String> {
+    let entry = Path::new(".").join(normalize(&options.path));
+    if !paths.contains(&entry) || entry.extension().is_none_or(|extension| extension != "rs") {
+        return Err(format!(
+            "{} is not a Rust file under the working directory",
+            options.path.display()
+        ));
+    }
+    let modules = Modules::new(&entry, paths);
+    // Finding dependents needs every file of the crate parsed; otherwise only the
+    // reached ones are, as they are met.
+    let mut parsed: HashMap<PathBuf, Edges> = if options.dependents {
+        modules
+            .files
+            .iter()
+            .map(|file| (file.to_path_buf(), modules.edges(root, file)))
+            .collect()
+    } else {
+        HashMap::new()
+    };
+
+    let mut reached = HashSet::from([entry.clone()]);
+    let mut visited = HashSet::new();
+    let mut queue = VecDeque::from([(entry.clone(), Reach::Module, 0)]);
+    while let Some((file, reach, depth)) = queue.pop_front() {
+        if !visited.insert((file.clone(), reach.clone())) {
+            continue;
+        }
+        reached.insert(file.clone());
+        if options.depth.is_some_and(|limit| depth >= limit) {
+            continue;
+        }
+        let edges = parsed
+            .entry(file.clone())
+            .or_insert_with(|| modules.edges(root, &file));
+        let declared = if reach == Reach::Module {
+            edges.declared.clone()
+        } else {
+            Vec::new()
+        };
+        let used = match &reach {
+            Reach::Item(name) => edges.imports.get(name).unwrap_or(&edges.used),
+            _ => &edges.used,
+        };
+        let next = declared
+            .into_iter()
+            .map(|file| (file, Reach::Module))
+            .chain(used.iter().map(|used| {
+                (
+                    used.file.clone(),
+                    used.item.clone().map_or(Reach::Whole, Reach::Item),
+                )
+            }));
+        queue.extend(next.map(|(file, reach)| (file, reach, depth + 1)));
+    }
+
+    if options.dependents {
+        // The crate root mostly re-exports, so only the names it re-exports from a
+        // dependent make its users dependents too; anything else that uses a
+        // dependent depends on it through its own items.
+        let mut visited = HashSet::new();
+        let mut queue = VecDeque::from([(entry, None, 0)]);
+        while let Some((file, exposed, depth)) = queue.pop_front() {
+            if !visited.insert(file.clone()) {
+                continue;
+            }
+            reached.insert(file.clone());
+            if options.depth.is_some_and(|limit| depth >= limit) {
+                continue;
+            }
+            let uses_file = |used: &Use| {
+                used.file == file
+                    && exposed.as_ref().is_none_or(|exposed: &HashSet<String>| {
+                        used.item.as_ref().is_none_or(|item| exposed.contains(item))
+                    })
+            };
+            for (user, edges) in &parsed {
+                if !edges.used.iter().any(uses_file) {
+                    continue;
+                }
+                let exposed = (*user == modules.crate_root).then(|| {
+                    edges
+                        .imports
+                        .iter()
+                        .filter(|(_, uses)| uses.iter().any(uses_file))
+                        .map(|(name, _)| name.clone())
+                        .collect()
+                });
+                queue.push_back((user.clone(), exposed, depth + 1));
+            }
+        }
+    }
+    Ok(reached)
+}
+
+/// How a file was reached, which decides which of its own edges are followed.
+#[derive(Clone, PartialEq, Eq, Hash)]
+enum Reach {
+    /// The entry or one of its submodules: declarations and uses.
+    Module,
+    /// Named as a whole module by a path: its uses.
+    Whole,
+    /// For one name: the file it is imported from, or else the module's uses.
+    Item(String),
+}
+
+/// A file named by a path, and the item of it the path names after the module.
+#[derive(Clone)]
+struct Use {
+    file: PathBuf,
+    item: Option<String>,
+}
+
+/// The files declared with `mod` and those named by `use` or qualified paths.
+#[derive(Default)]
+struct Edges {
+    declared: Vec<PathBuf>,
+    used: Vec<Use>,
+    /// What each name brought into scope by `use` refers to.
+    imports: HashMap<String, Vec<Use>>,
+}
+
+/// The Rust files of the crate containing the entry, and how module paths map to them.
+struct Modules<'a> {
+    /// The directory of the crate root's child modules, such as `./src`.
+    base: PathBuf,
+    /// The file `crate` refers to.
+    crate_root: PathBuf,
+    files: HashSet<&'a Path>,
+}
+
+impl<'a> Modules<'a> {
+    /// Finds the crate of `entry`. Files under `src`, outside `src/bin`, share the
+    /// library or main crate; any other file, such as a test or a binary, is a crate
+    /// root of its own whose modules sit next to it.
+    fn new(entry: &Path, paths: &'a [PathBuf]) -> Self {
+        let parent = entry.parent().unwrap_or_else(|| Path::new("."));
+        let src = entry
+            .ancestors()
+            .find(|dir| {
+                dir.file_name().is_some_and(|name| name == "src")
+                    && paths.contains(&dir.parent().unwrap_or(dir).join("Cargo.toml"))
+            })
+            .filter(|src| !entry.starts_with(src.join("bin")));
+        let (base, crate_root) = src.map_or_else(
+            || (parent.to_path_buf(), entry.to_path_buf()),
+            |src| {
+                let roots = ["lib.rs", "main.rs"].map(|name| src.join(name));
+                let crate_root = if roots.iter().any(|root| root == entry) {
+                    entry.to_path_buf()
+                } else {
+                    roots
+                        .into_iter()
+                        .find(|root| paths.contains(root))
+                        .unwrap_or_else(|| entry.to_path_buf())
+                };
+                (src.to_path_buf(), crate_root)
+            },
+        );
+        let files = paths
+            .iter()
+            .filter(|path| {
+                path.starts_with(&base)
+                    && path.extension().is_some_and(|extension| extension == "rs")
+                    && (src.is_none() || !path.starts_with(base.join("bin")))
+            })
+            .map(PathBuf::as_path)
+            .collect();
+        Self {
+            base,
+            crate_root,
+            files,
+        }
+    }
+
+    /// The module path of a file: `src/a/b.rs` and `src/a/b/mod.rs` are `a::b`.
+    fn module_of(&self, file: &Path) -> Vec<String> {
+        if file == self.crate_root {
+            return Vec::new();
+        }
+        let Ok(relative) = file.strip_prefix(&self.base) else {
+            return Vec::new();
+        };
+        let mut segments: Vec<String> = relative
+            .with_extension("")
+            .iter()
+            .map(|segment| segment.to_string_lossy().into_owned())
+            .collect();
+        if segments.last().is_some_and(|last| last == "mod") {
+            segments.pop();
+        }
+        segments
+    }
+
+    /// The file holding exactly the module at `segments`.
+    fn file_of(&self, segments: &[String]) -> Option<PathBuf> {
+        if segments.is_empty() {
+            return Some(self.crate_root.clone());
+        }
+        let path = segments
+            .iter()
+            .fold(self.base.clone(), |path, segment| path.join(segment));
+        [path.with_extension("rs"), path.join("mod.rs")]
+            .into_iter()
+            .find(|file| self.files.contains(file.as_path()))
+    }
+
+    /// The file of the longest prefix of `segments` that is a module file, and the
+    /// length of that prefix; the rest names items or modules written inline.
+    fn resolve(&self, segments: &[String]) -> Option<(PathBuf, usize)> {
+        (0..=segments.len())
+            .rev()
+            .find_map(|len| Some((self.file_of(&segments[..len])?, len)))
+    }
+
+    /// Parses `file` and resolves its `mod` declarations and crate-relative paths.
+    fn edges(&self, root: &Path, file: &Path) -> Edges {
+        let parsed = fs::read_to_string(root.join(file))
+            .map_err(|e| e.to_string())
+            .and_then(|source| syn::parse_file(&source).map_err(|e| e.to_string()))
+            .map(|syntax| {
+                let mut collector = Collector {
+                    modules: self,
+                    file,
+                    module: self.module_of(file),
+                    edges: Edges::default(),
+                };
+                collector.visit_file(&syntax);
+                collector.edges
+            });
+        // The parsed source stays in a thread-local span map until invalidated.
+        proc_macro2::extra::invalidate_current_thread_spans();
+        parsed.unwrap_or_else(|e| {
+            warn!(
+                "Could not follow the modules of [{}]: {e}",
+                file.to_string_lossy()
+            );
+            Edges::default()
+        })
+    }
+}
+
+/// Walks one file, tracking the inline module each item is in.
+struct Collector<'a, 'b> {
+    modules: &'a Modules<'b>,
+    file: &'a Path,
+    module: Vec<String>,
+    edges: Edges,
+}
+
+impl Collector<'_, '_> {
+    /// The file and item named by a path written in the current module, if it is in
+    /// this crate: under `crate::`, `self::`, `super::` or a child module.
+    fn resolve(&self, segments: &[String]) -> Option<Use> {
+        let absolute = match segments.first().map(String::as_str)? {
+            "crate" => segments[1..].to_vec(),
+            "self" => [self.module.as_slice(), &segments[1..]].concat(),
+            "super" => {
+                let supers = segments
+                    .iter()
+                    .take_while(|segment| *segment == "super")
+                    .count();
+                let module = &self.module[..self.module.len().saturating_sub(supers)];
+                [module, &segments[supers..]].concat()
+            }
+            first => {
+                let child = [self.module.as_slice(), &[first.to_string()]].concat();
+                self.modules.file_of(&child)?;
+                [self.module.as_slice(), segments].concat()
+            }
+        };
+        let (file, len) = self.modules.resolve(&absolute)?;
+        (file != self.file).then(|| Use {
+            file,
+            item: absolute.get(len).cloned(),
+        })
+    }
+}
+
+impl<'ast> Visit<'ast> for Collector<'_, '_> {
+    fn visit_item_mod(&mut self, item: &'ast ItemMod) {
+        let name = item.ident.to_string();
+        if item.content.is_some() {
+            self.module.push(name);
+            visit::visit_item_mod(self, item);
+            self.module.pop();
+            return;
+        }
+        let declared = match path_attribute(item) {
+            Some(path) => {
+                let dir = self.file.parent().unwrap_or_else(|| Path::new("."));
+                Some(Path::new(".").join(normalize(&dir.join(path))))
+                    .filter(|file| self.modules.files.contains(file.as_path()))
+            }
+            None => self
+                .modules
+                .file_of(&[self.module.as_slice(), &[name]].concat()),
+        };
+        self.edges.declared.extend(declared);
+    }
+
+    fn visit_item_use(&mut self, item: &'ast ItemUse) {
+        let mut imports = Vec::new();
+        use_paths(&item.tree, &mut Vec::new(), &mut imports);
+        for (path, name) in imports {
+            if let Some(used) = self.resolve(&path) {
+                if let Some(name) = name {
+                    self.edges
+                        .imports
+                        .entry(name)
+                        .or_default()
+                        .push(used.clone());
+                }
+                self.edges.used.push(used);
+            }
+        }
+    }
+
+    fn visit_path(&mut self, path: &'ast syn::Path) {
+        if path.leading_colon.is_none() && path.segments.len() > 1 {
+            let segments: Vec<String> = path
+                .segments
+                .iter()
+                .map(|segment| segment.ident.to_string())
+                .collect();
+            self.edges.used.extend(self.resolve(&segments));
+        }
+        visit::visit_path(self, path);
+    }
+}
+
+/// Expands a `use` tree into every path it imports and the name it binds, which
+/// globs do not have.
+fn use_paths(
+    tree: &UseTree,
+    prefix: &mut Vec<String>,
+    paths: &mut Vec<(Vec<String>, Option<String>)>,
+) {
+    match tree {
+        UseTree::Path(path) => {
+            prefix.push(path.ident.to_string());
+            use_paths(&path.tree, prefix, paths);
+            prefix.pop();
+        }
+        UseTree::Name(name) if name.ident == "self" => {
+            paths.push((prefix.clone(), prefix.last().cloned()));
+        }
+        UseTree::Name(name) => {
+            let name = name.ident.to_string();
+            paths.push((
+                [prefix.as_slice(), std::slice::from_ref(&name)].concat(),
+                Some(name),
+            ));
+        }
+        UseTree::Rename(rename) => {
+            let path = [prefix.as_slice(), &[rename.ident.to_string()]].concat();
+            paths.push((path, Some(rename.rename.to_string())));
+        }
+        UseTree::Glob(_) => paths.push((prefix.clone(), None)),
+        UseTree::Group(group) => {
+            for tree in &group.items {
+                use_paths(tree, prefix, paths);
+            }
+        }
+    }
+}
+
+/// The value of a `#[path = "..."]` attribute.
+fn path_attribute(item: &ItemMod) -> Option<String> {
+    item.attrs.iter().find_map(|attr| match &attr.meta {
+        Meta::NameValue(meta) if meta.path.is_ident("path") => match &meta.value {
+            Expr::Lit(expr) => match &expr.lit {
+                Lit::Str(path) => Some(path.value()),
+                _ => None,
+            },
+            _ => None,
+        },
+        _ => None,
+    })
+}
+
+/// Removes `.` components and folds `..` into the component before it.
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            component => normalized.push(component),
+        }
+    }
+    normalized
+}
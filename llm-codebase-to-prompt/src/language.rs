@@ -0,0 +1,80 @@
+use std::path::Path;
+
+/// Guesses the language of a file from its name, using the identifiers Markdown
+/// renderers understand for fenced code blocks.
+#[must_use]
+pub fn detect(path: &Path) -> Option<&'static str> {
+    let name = path.file_name()?.to_string_lossy().to_lowercase();
+    match name.as_str() {
+        "dockerfile" | "containerfile" => return Some("dockerfile"),
+        "makefile" | "gnumakefile" => return Some("makefile"),
+        "cmakelists.txt" => return Some("cmake"),
+        "cargo.lock" => return Some("toml"),
+        ".gitignore" | ".dockerignore" | ".promptignore" | ".ignore" => return Some("gitignore"),
+        ".env" => return Some("dotenv"),
+        _ => {}
+    }
+
+    let extension = path.extension()?.to_string_lossy().to_lowercase();
+    let language = match extension.as_str() {
+        "rs" => "rust",
+        "toml" => "toml",
+        "py" | "pyi" => "python",
+        "js" | "mjs" | "cjs" => "javascript",
+        "jsx" => "jsx",
+        "ts" | "mts" | "cts" => "typescript",
+        "tsx" => "tsx",
+        "go" => "go",
+        "java" => "java",
+        "kt" | "kts" => "kotlin",
+        "scala" => "scala",
+        "c" | "h" => "c",
+        "cc" | "cpp" | "cxx" | "hh" | "hpp" | "hxx" => "cpp",
+        "cs" => "csharp",
+        "swift" => "swift",
+        "m" => "objectivec",
+        "rb" => "ruby",
+        "php" => "php",
+        "pl" | "pm" => "perl",
+        "lua" => "lua",
+        "r" => "r",
+        "jl" => "julia",
+        "dart" => "dart",
+        "ex" | "exs" => "elixir",
+        "erl" | "hrl" => "erlang",
+        "hs" => "haskell",
+        "ml" | "mli" => "ocaml",
+        "clj" | "cljs" | "edn" => "clojure",
+        "zig" => "zig",
+        "nix" => "nix",
+        "sh" | "bash" => "bash",
+        "zsh" => "zsh",
+        "fish" => "fish",
+        "ps1" | "psm1" => "powershell",
+        "bat" | "cmd" => "batch",
+        "sql" => "sql",
+        "html" | "htm" => "html",
+        "css" => "css",
+        "scss" => "scss",
+        "sass" => "sass",
+        "less" => "less",
+        "vue" => "vue",
+        "svelte" => "svelte",
+        "json" | "jsonc" | "ipynb" => "json",
+        "yaml" | "yml" => "yaml",
+        "xml" | "xsd" | "svg" => "xml",
+        "ini" | "cfg" => "ini",
+        "proto" => "protobuf",
+        "graphql" | "gql" => "graphql",
+        "tf" | "hcl" => "hcl",
+        "md" | "markdown" => "markdown",
+        "rst" => "rst",
+        "tex" => "latex",
+        "csv" => "csv",
+        "tsv" => "tsv",
+        "diff" | "patch" => "diff",
+        "txt" => "text",
+        _ => return None,
+    };
+    Some(language)
+}
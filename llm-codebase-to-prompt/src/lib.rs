@@ -1,61 +1,1058 @@
-use ignore::WalkBuilder;
-use log::{info, warn, error};
+pub mod apply;
+pub mod budget;
+pub mod builder;
+pub mod chunk;
+pub mod config;
+pub mod content;
+pub mod data;
+pub mod delta;
+pub mod error;
+pub mod format;
+pub mod git;
+pub mod graph;
+pub mod language;
+pub mod limit;
+pub mod minify;
+pub mod outline;
+pub mod rank;
+pub mod secrets;
+pub mod select;
+pub mod stats;
+pub mod template;
+pub mod tokens;
+pub mod tree;
+pub mod walk;
+pub mod watch;
+
+#[cfg(feature = "logging")]
+use log::info;
+use log::warn;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt::{self, Write as _};
 use std::fs;
 use std::io::Write;
-use glob::Pattern;
-
-pub fn process_files(
-    pattern: &str,
-    context: Option<&str>,
-    default_context: &str,
-    output_file: &mut fs::File,
-    no_recursive: bool,
-) -> Result<(), String> {
-    #[cfg(feature = "logging")]
-    info!("Starting to process files with pattern: {}", pattern);
+use std::path::{Path, PathBuf};
+
+use content::Content;
+use git::ChangeSet;
+use rayon::prelude::*;
+use secrets::Scanner;
+use select::Selector;
+
+pub use budget::OverBudget;
+pub use builder::PromptBuilder;
+pub use chunk::ChunkLimit;
+pub use config::{Config, Profile, SectionConfig};
+pub use data::DataOptions;
+pub use delta::{DeltaOptions, Manifest};
+pub use error::Error;
+pub use format::OutputFormat;
+pub use git::{ChangeSource, GitOptions};
+pub use graph::EntryOptions;
+pub use limit::{CappedFile, FileLimit, FileLimits, LimitRule};
+pub use minify::{MinifyOptions, MinifyStage};
+pub use secrets::{SecretFinding, SecretOptions};
+pub use select::Selection;
+pub use stats::{LanguageStats, Stats};
+pub use tokens::Tokenizer;
+pub use tree::{TreeAnnotation, TreeOptions};
+pub use walk::IgnoreMode;
+
+/// A file matched by a section's pattern, read and ready to go into the prompt.
+#[derive(Clone)]
+pub struct SourceFile {
+    pub path: PathBuf,
+    pub contents: String,
+    /// Tokens taken by the file once written, header included.
+    pub tokens: usize,
+    /// The first and last line held, when this is only part of the file. Lines are
+    /// counted in the contents as written, after any outline or minification.
+    pub line_range: Option<(usize, usize)>,
+}
+
+impl SourceFile {
+    /// The name the file is shown under in the prompt.
+    #[must_use]
+    pub fn display_name(&self) -> String {
+        let path = self.path.to_string_lossy();
+        match self.line_range {
+            Some((first, last)) => format!("{path} (lines {first}-{last})"),
+            None => path.into_owned(),
+        }
+    }
+
+    /// Writes the file the way it appears in the prompt.
+    fn render(&self, format: OutputFormat) -> String {
+        format.render_named(
+            &self.display_name(),
+            language::detect(&self.path),
+            &self.contents,
+        )
+    }
+}
+
+/// One part of the prompt: every file selected by the `include` and `exclude` globs,
+/// followed by a context paragraph.
+pub struct Section<'a> {
+    pub name: &'a str,
+    pub include: &'a [String],
+    pub exclude: &'a [String],
+    pub context: Option<&'a str>,
+    pub default_context: &'a str,
+}
+
+impl Section<'_> {
+    fn context(&self) -> &str {
+        self.context.unwrap_or(self.default_context)
+    }
+
+    /// The context as written after the files of the section.
+    fn written_context(&self) -> String {
+        format!("{}\n\n", self.context())
+    }
+}
 
-    let mut builder = WalkBuilder::new(".");
-    if no_recursive {
-        builder.max_depth(Some(1));
+/// Settings shared by every section of a prompt.
+#[derive(Default)]
+pub struct Options {
+    pub ignore_mode: IgnoreMode,
+    /// How deep to descend below the working directory; `None` is unlimited.
+    pub max_depth: Option<usize>,
+    /// Also walk hidden files and directories, such as `.env` or `.github`.
+    pub hidden: bool,
+    pub format: OutputFormat,
+    pub tokenizer: Tokenizer,
+    /// Upper bound on the size of the whole prompt, in tokens.
+    pub max_tokens: Option<usize>,
+    pub over_budget: OverBudget,
+    /// Draw the project layout at the top of the prompt.
+    pub tree: Option<TreeOptions>,
+    /// Only include the files touched in a git revision range.
+    pub git: Option<GitOptions>,
+    /// Gitignore-style globs of Rust files reduced to an outline: signatures, type
+    /// definitions and doc comments, with function bodies, initializers and other
+    /// comments elided.
+    pub outline: Vec<String>,
+    /// Strip comments, banners and whitespace to save tokens.
+    pub minify: Option<MinifyOptions>,
+    /// Redact keys, tokens and `.env` values before they reach the prompt.
+    pub secrets: Option<SecretOptions>,
+    /// Rank the files of the first section against this query with BM25, keeping only
+    /// those that match, best first, and as many as fit `max_tokens`.
+    pub query: Option<String>,
+    /// Only include the Rust files reachable from an entry file through `mod`
+    /// declarations and crate paths.
+    pub entry: Option<EntryOptions>,
+    /// Prefix every line with its number in the file as read, which outline and
+    /// minify keep. Summarized data files are left unnumbered.
+    pub line_numbers: bool,
+    /// Render notebooks as their cells, and CSV, TSV and JSON data as a schema and a
+    /// sample.
+    pub data: Option<DataOptions>,
+    /// A Jinja-like template laying out the whole prompt instead of the default
+    /// layout, see [`template::render`].
+    pub template: Option<String>,
+    /// Only emit the files added or modified since the prompt recorded in a manifest,
+    /// under a note listing what changed.
+    pub since_last: Option<DeltaOptions>,
+    /// How large each file may be before it is cut to its head and tail.
+    pub file_limits: FileLimits,
+    /// Open the prompt with its files, lines and tokens per language, its largest files
+    /// and its total tokens.
+    pub stats: bool,
+}
+
+impl Options {
+    /// Whether every file can be written as soon as it is read. The budget, the query,
+    /// a template, an update, the tree and the statistics need every file first, and
+    /// failing on secrets must happen before anything is written.
+    pub(crate) fn streams(&self) -> bool {
+        self.max_tokens.is_none()
+            && self.query.is_none()
+            && self.template.is_none()
+            && self.since_last.is_none()
+            && self.tree.is_none()
+            && !self.stats
+            && !self.secrets.as_ref().is_some_and(|secrets| secrets.fail)
     }
-    let walker = builder.build();
+}
+
+/// How many files each thread reads ahead before the batch is taken in order; bounds
+/// how many file contents are held at once while streaming.
+const READ_AHEAD: usize = 16;
+
+/// Per-run state shared while reading the files of every section.
+struct Pipeline<'a> {
+    root: &'a Path,
+    options: &'a Options,
+    changes: Option<ChangeSet>,
+    /// The Rust files reachable from the entry, when there is one.
+    reachable: Option<HashSet<PathBuf>>,
+    outline: Selector,
+    limits: limit::Limits,
+    secrets: Option<Scanner>,
+}
+
+/// What went into a generated prompt.
+pub struct Report {
+    /// Every file written, in prompt order.
+    pub included: Vec<IncludedFile>,
+    /// Files whose contents were left out, with the reason.
+    pub skipped: Vec<SkippedFile>,
+    /// Files dropped or truncated to stay within `max_tokens`.
+    pub budget_changes: Vec<String>,
+    /// Tokens saved by minification, per file.
+    pub minified: Vec<(PathBuf, usize)>,
+    /// Files cut to their head and tail to stay under their limit.
+    pub capped: Vec<CappedFile>,
+    /// Secrets replaced by a placeholder.
+    pub secrets: Vec<SecretFinding>,
+    /// Files of the first section that matched the query, best first.
+    pub ranking: Vec<RankedFile>,
+    /// Sections whose globs matched none of the walked files.
+    pub unmatched: Vec<String>,
+    /// Tokens of the whole prompt, contexts and header included.
+    pub total: usize,
+    /// The files of the prompt by language, and the largest ones.
+    pub stats: Stats,
+    /// The files the prompt holds, to save for the next update, with `since_last` only.
+    pub manifest: Option<Manifest>,
+}
+
+/// A file written to the prompt.
+pub struct IncludedFile {
+    /// Relative to the root, starting with `./`.
+    pub path: PathBuf,
+    /// Size of the contents as written, after any transformation.
+    pub bytes: usize,
+    /// Tokens taken by the file once written, header included.
+    pub tokens: usize,
+}
+
+impl IncludedFile {
+    fn new(file: &SourceFile) -> Self {
+        Self {
+            path: file.path.clone(),
+            bytes: file.contents.len(),
+            tokens: file.tokens,
+        }
+    }
+}
+
+/// A file scored against the query.
+pub struct RankedFile {
+    pub path: PathBuf,
+    pub score: f64,
+    /// Whether it fit in the budget.
+    pub included: bool,
+}
+
+/// A selected file whose contents are not in the prompt.
+pub struct SkippedFile {
+    pub path: PathBuf,
+    pub reason: SkipReason,
+}
+
+/// Why a file's contents were left out.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SkipReason {
+    /// Replaced by a placeholder line.
+    Binary { size: u64, mime: String },
+    /// Reading the file failed.
+    Unreadable(String),
+}
+
+impl fmt::Display for SkipReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Binary { size, mime } => write!(f, "binary, {size} bytes, {mime}"),
+            Self::Unreadable(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+/// What happened to individual files while they were read.
+#[derive(Default)]
+struct Notes {
+    skipped: Vec<SkippedFile>,
+    minified: Vec<(PathBuf, usize)>,
+    capped: Vec<CappedFile>,
+    secrets: Vec<SecretFinding>,
+    documents: Vec<rank::Document>,
+    unmatched: Vec<String>,
+}
+
+/// A prompt whose files have been read, transformed and fitted to the budget.
+pub struct Prompt {
+    /// The update note, statistics, project tree and git changes, written before the
+    /// first section.
+    pub header: String,
+    /// The project tree as drawn, without the format's wrapping; empty when not asked for.
+    pub tree: String,
+    /// The files touched in the git revision range, in git mode.
+    pub changes: Option<ChangeSet>,
+    pub sections: Vec<PromptSection>,
+    pub report: Report,
+}
+
+/// The files of one section, followed by its context paragraph.
+pub struct PromptSection {
+    pub name: String,
+    pub files: Vec<SourceFile>,
+    pub context: String,
+}
+
+impl Prompt {
+    /// Writes the whole prompt.
+    ///
+    /// # Errors
+    /// Returns any error from writing to `output`.
+    pub fn write(&self, output: &mut impl Write, format: OutputFormat) -> std::io::Result<()> {
+        write!(output, "{}", self.header)?;
+        for section in &self.sections {
+            format.write_files(output, &section.files)?;
+            writeln!(output, "{}\n", section.context)?;
+        }
+        Ok(())
+    }
+}
+
+impl<'a> Pipeline<'a> {
+    /// Looks up the git changes, compiles the patterns and walks `root`, returning the
+    /// walked paths along with the pipeline.
+    fn new(root: &'a Path, options: &'a Options) -> Result<(Self, Vec<PathBuf>), Error> {
+        let mut pipeline = Self {
+            root,
+            options,
+            changes: options
+                .git
+                .as_ref()
+                .map(|git| git::changes(root, git))
+                .transpose()
+                .map_err(Error::Git)?,
+            reachable: None,
+            outline: Selector::new(&options.outline, &[]).map_err(Error::Pattern)?,
+            limits: limit::Limits::new(&options.file_limits).map_err(Error::Pattern)?,
+            secrets: options
+                .secrets
+                .as_ref()
+                .map(Scanner::new)
+                .transpose()
+                .map_err(Error::Pattern)?,
+        };
+        let paths = walk::files(root, options.ignore_mode, options.max_depth, options.hidden)
+            .map_err(Error::Walk)?;
+        pipeline.reachable = options
+            .entry
+            .as_ref()
+            .map(|entry| graph::reachable(root, &paths, entry))
+            .transpose()
+            .map_err(Error::Entry)?;
+        Ok((pipeline, paths))
+    }
+}
+
+/// Writes the prompt to `output` file by file, as each batch is read, without holding
+/// more than a batch of files at once. Only for options that [`Options::streams`].
+fn stream_prompt(
+    root: &Path,
+    sections: &[Section],
+    options: &Options,
+    output: &mut impl Write,
+) -> Result<Report, Error> {
+    let (mut pipeline, paths) = Pipeline::new(root, options)?;
+    let mut notes = Notes::default();
+    let mut change_notes = Notes::default();
+    if let (Some(scanner), Some(changes)) = (&pipeline.secrets, &mut pipeline.changes) {
+        redact_changes(scanner, changes, &mut change_notes);
+    }
+    let changes = pipeline
+        .changes
+        .as_ref()
+        .map(|changes| render_changes(changes, options.format))
+        .unwrap_or_default();
+    write!(output, "{changes}")?;
+
+    let mut total = options.tokenizer.count(&changes);
+    let mut included = Vec::new();
+    let mut stats = Stats::default();
+    for section in sections {
+        let selected = select_files(section, &paths, &pipeline, &mut notes)?;
+        let mut count = 0;
+        read_files(&selected, &pipeline, false, &mut notes, |file| {
+            options.format.write_file(output, &file, count)?;
+            count += 1;
+            total += file.tokens;
+            stats.add(&file);
+            included.push(IncludedFile::new(&file));
+            Ok(())
+        })?;
+        options.format.finish_files(output, count)?;
+        let context = section.written_context();
+        write!(output, "{context}")?;
+        total += options.tokenizer.count(&context);
+    }
+    notes.secrets.extend(change_notes.secrets);
+    stats.total = total;
+    Ok(Report {
+        included,
+        skipped: notes.skipped,
+        budget_changes: Vec::new(),
+        minified: notes.minified,
+        capped: notes.capped,
+        secrets: notes.secrets,
+        ranking: Vec::new(),
+        unmatched: notes.unmatched,
+        total,
+        stats,
+        manifest: None,
+    })
+}
+
+/// Reads and transforms the files of every section under `root` and fits them to the
+/// token budget.
+fn build_prompt(root: &Path, sections: &[Section], options: &Options) -> Result<Prompt, Error> {
+    let (mut pipeline, paths) = Pipeline::new(root, options)?;
+    let mut files = Vec::new();
+    let mut notes = Notes::default();
+    for (index, section) in sections.iter().enumerate() {
+        let ranked = index == 0 && options.query.is_some();
+        files.push(collect_files(
+            section, &paths, &pipeline, ranked, &mut notes,
+        )?);
+    }
+    if let (Some(scanner), Some(changes)) = (&pipeline.secrets, &mut pipeline.changes) {
+        redact_changes(scanner, changes, &mut notes);
+    }
+    if options.secrets.as_ref().is_some_and(|secrets| secrets.fail) && !notes.secrets.is_empty() {
+        return Err(Error::Secrets(notes.secrets));
+    }
+    let comparison = compare_with_last(&mut files, options)?;
+    let update = comparison
+        .as_ref()
+        .and_then(|comparison| comparison.delta.as_ref())
+        .map(delta::Delta::render)
+        .unwrap_or_default();
+    let changes = pipeline
+        .changes
+        .as_ref()
+        .map(|changes| render_changes(changes, options.format))
+        .unwrap_or_default();
+    let tree_paths = if options.tree.is_some() {
+        paths.as_slice()
+    } else {
+        &[]
+    };
+    let contexts: usize = sections
+        .iter()
+        .map(|section| options.tokenizer.count(&section.written_context()))
+        .sum();
+    let overhead = |files: &[Vec<SourceFile>]| {
+        let tree = wrap_tree(&project_tree(root, tree_paths, files, options), options);
+        // The statistics of the final files only differ from these in their numbers.
+        let stats = wrap_stats(&Stats::new(files, 0), options);
+        contexts
+            + options.tokenizer.count(&update)
+            + options.tokenizer.count(&stats)
+            + options.tokenizer.count(&tree)
+            + options.tokenizer.count(&changes)
+    };
+    let ranking = match &options.query {
+        Some(query) => {
+            let overhead = overhead(&files);
+            rank_files(
+                query,
+                &mut files,
+                &notes.documents,
+                overhead,
+                options.max_tokens,
+            )
+        }
+        None => Vec::new(),
+    };
+    let overhead = overhead(&files);
+
+    let budget_changes = match options.max_tokens {
+        Some(max_tokens) => budget::enforce(
+            &mut files,
+            overhead,
+            max_tokens,
+            options.over_budget,
+            &options.tokenizer,
+        )
+        .map_err(Error::OverBudget)?,
+        None => Vec::new(),
+    };
+
+    let tree = project_tree(root, tree_paths, &files, options);
+    let total = overhead
+        + files
+            .iter()
+            .flatten()
+            .map(|file| file.tokens)
+            .sum::<usize>();
+    let stats = Stats::new(&files, total);
+    let header = update + &wrap_stats(&stats, options) + &wrap_tree(&tree, options) + &changes;
+    let report = Report {
+        included: files.iter().flatten().map(IncludedFile::new).collect(),
+        skipped: notes.skipped,
+        budget_changes,
+        minified: notes.minified,
+        capped: notes.capped,
+        secrets: notes.secrets,
+        ranking,
+        unmatched: notes.unmatched,
+        manifest: comparison.map(|comparison| comparison.manifest(&files)),
+        total,
+        stats,
+    };
+    Ok(Prompt {
+        header,
+        tree,
+        changes: pipeline.changes,
+        sections: prompt_sections(sections, files),
+        report,
+    })
+}
+
+/// The files of a prompt compared with the last one, with `since_last`.
+struct Comparison {
+    /// The entry of every file as read, before any was left out.
+    current: BTreeMap<PathBuf, delta::Entry>,
+    /// The manifest of the last prompt, if there was one.
+    previous: Option<Manifest>,
+    delta: Option<delta::Delta>,
+}
+
+impl Comparison {
+    /// The manifest of the prompt holding `files`, to compare the next one with.
+    fn manifest(&self, files: &[Vec<SourceFile>]) -> Manifest {
+        Manifest::record(
+            &self.current,
+            files,
+            self.previous.as_ref(),
+            self.delta.as_ref(),
+        )
+    }
+}
 
-    let glob_pattern = Pattern::new(pattern).map_err(|e| e.to_string())?;
+/// Pairs each section with its files, in prompt order.
+fn prompt_sections(sections: &[Section], files: Vec<Vec<SourceFile>>) -> Vec<PromptSection> {
+    sections
+        .iter()
+        .zip(files)
+        .map(|(section, files)| PromptSection {
+            name: section.name.to_string(),
+            files,
+            context: section.context().to_string(),
+        })
+        .collect()
+}
+
+/// With `since_last`, records what every file reads as, then leaves out those that did
+/// not change since the last prompt.
+fn compare_with_last(
+    files: &mut [Vec<SourceFile>],
+    options: &Options,
+) -> Result<Option<Comparison>, Error> {
+    let Some(since_last) = &options.since_last else {
+        return Ok(None);
+    };
+    let current = files
+        .iter()
+        .flatten()
+        .map(|file| {
+            (
+                file.path.clone(),
+                delta::Entry::new(&file.contents, since_last.diffs),
+            )
+        })
+        .collect();
+    let previous = Manifest::load(&since_last.manifest).map_err(Error::Manifest)?;
+    let delta = previous.as_ref().map(|previous| {
+        delta::Delta::apply(
+            files,
+            previous,
+            since_last.diffs,
+            options.format,
+            &options.tokenizer,
+        )
+    });
+    Ok(Some(Comparison {
+        current,
+        previous,
+        delta,
+    }))
+}
+
+/// Orders the first section's files by their BM25 score against `query`, drops those
+/// that do not match, and, under `max_tokens`, keeps the best ones that fit next to
+/// the other sections and `overhead`.
+fn rank_files(
+    query: &str,
+    files: &mut [Vec<SourceFile>],
+    documents: &[rank::Document],
+    overhead: usize,
+    max_tokens: Option<usize>,
+) -> Vec<RankedFile> {
+    let Some((first, others)) = files.split_first_mut() else {
+        return Vec::new();
+    };
+    let scores = rank::rank(query, documents);
+    let mut remaining = max_tokens.map(|max_tokens| {
+        max_tokens.saturating_sub(
+            overhead
+                + others
+                    .iter()
+                    .flatten()
+                    .map(|file| file.tokens)
+                    .sum::<usize>(),
+        )
+    });
+
+    let mut by_path: HashMap<PathBuf, SourceFile> = first
+        .drain(..)
+        .map(|file| (file.path.clone(), file))
+        .collect();
+    let mut ranking = Vec::new();
+    for (path, score) in scores {
+        let Some(file) = by_path.remove(&path) else {
+            continue;
+        };
+        let included = remaining.is_none_or(|remaining| file.tokens <= remaining);
+        if included {
+            if let Some(remaining) = &mut remaining {
+                *remaining -= file.tokens;
+            }
+            #[cfg(feature = "logging")]
+            info!("Ranked [{}] with score {score:.2}", path.to_string_lossy());
+            first.push(file);
+        }
+        ranking.push(RankedFile {
+            path,
+            score,
+            included,
+        });
+    }
+    ranking
+}
 
-    for result in walker {
-        match result {
-            Ok(entry) => {
-                let file_path = entry.path();
+/// Applies the per-file content stages to a text file.
+fn transform(pipeline: &Pipeline, path: &Path, mut text: String, notes: &mut Notes) -> String {
+    if let Some(data_options) = &pipeline.options.data {
+        match data::extract(path, &text, data_options) {
+            Ok(Some(summary)) => {
                 #[cfg(feature = "logging")]
-                info!("Processing file: {}", file_path.to_string_lossy());
-
-                if file_path.is_file() && glob_pattern.matches_path(file_path) {
-                    match fs::read_to_string(&file_path) {
-                        Ok(contents) => {
-                            writeln!(output_file, "// {}\n{}\n", file_path.to_string_lossy(), contents)
-                                .map_err(|e| e.to_string())?;
-                        }
-                        Err(e) => {
-                            if e.kind() == std::io::ErrorKind::PermissionDenied {
-                                warn!("Warning: [{}] permission denied error.", file_path.to_string_lossy());
-                            } else {
-                                error!("Error reading file [{}]: {}", file_path.to_string_lossy(), e);
-                                return Err(e.to_string());
-                            }
-                        }
-                    }
-                }
+                info!("Summarized data file [{}]", path.to_string_lossy());
+                return summary;
+            }
+            Ok(None) => {}
+            Err(e) => warn!(
+                "Could not summarize [{}], keeping full contents: {e}",
+                path.to_string_lossy()
+            ),
+        }
+    }
+    // The zero-based line of the file as read that each line of `text` came from.
+    let mut origins: Vec<usize> = (0..text.split_inclusive('\n').count()).collect();
+    let is_rust = path.extension().is_some_and(|extension| extension == "rs");
+    if is_rust && pipeline.outline.select(path).is_included() {
+        match outline::outline_rust_lines(&text) {
+            Ok(lines) => {
+                #[cfg(feature = "logging")]
+                info!("Outlined [{}]", path.to_string_lossy());
+                text = join_lines(lines, &mut origins, text.ends_with('\n'));
             }
-            Err(e) => {
-                error!("Error walking directory: {}", e);
-                return Err(e.to_string());
-            },
+            Err(e) => warn!(
+                "Could not outline [{}], keeping full contents: {e}",
+                path.to_string_lossy()
+            ),
+        }
+    }
+    if let Some(minify_options) = &pipeline.options.minify {
+        let lines = minify::minify_lines(path, &text, minify_options);
+        let minified = join_lines(lines, &mut origins, text.ends_with('\n'));
+        let tokenizer = &pipeline.options.tokenizer;
+        let saved = tokenizer
+            .count(&text)
+            .saturating_sub(tokenizer.count(&minified));
+        #[cfg(feature = "logging")]
+        info!(
+            "Minified [{}], saving {saved} tokens",
+            path.to_string_lossy()
+        );
+        notes.minified.push((path.to_path_buf(), saved));
+        text = minified;
+    }
+    if pipeline.options.line_numbers {
+        text = number_lines(&text, &origins);
+    }
+    text
+}
+
+/// Cuts a transformed file to its head and tail when it is over its limit.
+fn cap_file(pipeline: &Pipeline, path: &Path, text: String, notes: &mut Notes) -> String {
+    let Some(limit) = pipeline.limits.of(path) else {
+        return text;
+    };
+    match limit::cap(&text, limit, &pipeline.options.tokenizer) {
+        Some((capped, omitted)) => {
+            warn!(
+                "Omitting {omitted} lines of [{}] to stay under {limit}",
+                path.to_string_lossy()
+            );
+            let lines = text.lines().count();
+            notes.capped.push(CappedFile {
+                path: path.to_path_buf(),
+                limit,
+                lines,
+                omitted,
+            });
+            capped
+        }
+        None => text,
+    }
+}
+
+/// Joins lines taken from a text whose lines came from `origins`, and maps `origins`
+/// to the lines kept.
+fn join_lines(
+    lines: Vec<(usize, String)>,
+    origins: &mut Vec<usize>,
+    trailing_newline: bool,
+) -> String {
+    *origins = lines
+        .iter()
+        .map(|(number, _)| origins.get(*number).copied().unwrap_or(*number))
+        .collect();
+    let mut joined = lines
+        .into_iter()
+        .map(|(_, line)| line)
+        .collect::<Vec<_>>()
+        .join("\n");
+    if trailing_newline && !joined.is_empty() {
+        joined.push('\n');
+    }
+    joined
+}
+
+/// Prefixes each line with its one-based number in the original file, right-aligned,
+/// followed by `| `: `  42 | let x = 1;`.
+fn number_lines(text: &str, origins: &[usize]) -> String {
+    let width = origins
+        .iter()
+        .max()
+        .map_or(1, |last| (last + 1).to_string().len());
+    let mut numbered = String::with_capacity(text.len() + origins.len() * (width + 3));
+    for (index, line) in text.lines().enumerate() {
+        let number = origins.get(index).map_or(index, |origin| *origin) + 1;
+        if line.is_empty() {
+            let _ = writeln!(numbered, "{number:>width$} |");
+        } else {
+            let _ = writeln!(numbered, "{number:>width$} | {line}");
         }
     }
-    if let Some(context) = context {
-        writeln!(output_file, "{}", context).map_err(|e| e.to_string())?;
+    if !text.ends_with('\n') {
+        numbered.pop();
+    }
+    numbered
+}
+
+/// Draws the project tree over `paths`, marking which of them are in `files`.
+/// Empty when no tree was asked for.
+fn project_tree(
+    root: &Path,
+    paths: &[PathBuf],
+    files: &[Vec<SourceFile>],
+    options: &Options,
+) -> String {
+    let Some(tree_options) = &options.tree else {
+        return String::new();
+    };
+    let included: HashMap<&Path, &SourceFile> = files
+        .iter()
+        .flatten()
+        .map(|file| (file.path.as_path(), file))
+        .collect();
+    let entries: Vec<tree::TreeEntry> = paths
+        .iter()
+        .map(|path| {
+            let file = included.get(path.as_path());
+            tree::TreeEntry {
+                path: path.clone(),
+                included: file.is_some(),
+                size: fs::metadata(root.join(path)).map_or(0, |metadata| metadata.len()),
+                lines: file.map(|file| file.contents.lines().count()),
+                tokens: file.map(|file| file.tokens),
+            }
+        })
+        .collect();
+    tree::render(&entries, tree_options)
+}
+
+/// The project tree as the output format writes it, if there is one.
+fn wrap_tree(tree: &str, options: &Options) -> String {
+    if options.tree.is_some() {
+        options.format.render_tree(tree)
+    } else {
+        String::new()
+    }
+}
+
+/// The statistics as the output format writes them, if they were asked for.
+fn wrap_stats(stats: &Stats, options: &Options) -> String {
+    if options.stats {
+        options.format.render_stats(&stats.to_string())
     } else {
-        writeln!(output_file, "{}", default_context).map_err(|e| e.to_string())?;
+        String::new()
     }
-    writeln!(output_file).map_err(|e| e.to_string())
+}
+
+/// Redacts the diff and the original versions of the changed files.
+fn redact_changes(scanner: &Scanner, changes: &mut ChangeSet, notes: &mut Notes) {
+    let (patch, findings) = scanner.redact(
+        Path::new(&format!("{}.diff", changes.label)),
+        &changes.patch,
+    );
+    changes.patch = patch;
+    notes.secrets.extend(findings);
+    for file in &mut changes.files {
+        if let Some(original) = file.original.take() {
+            let name = format!("{}:{}", changes.base, file.path.display());
+            let (original, findings) = scanner.redact(Path::new(&name), &original);
+            file.original = Some(original);
+            notes.secrets.extend(findings);
+        }
+    }
+}
+
+/// Lists the changed files, followed by the diff and the original versions when
+/// they were asked for.
+fn render_changes(changes: &ChangeSet, format: OutputFormat) -> String {
+    let mut rendered = format!("The following files changed in {}:\n", changes.label);
+    for file in &changes.files {
+        let _ = writeln!(rendered, "{} {}", file.status, file.path.display());
+    }
+    rendered.push('\n');
+
+    if !changes.patch.is_empty() {
+        let name = PathBuf::from(format!("{}.diff", changes.label));
+        rendered.push_str(&format.render_file(&name, &changes.patch));
+        rendered.push('\n');
+    }
+    for file in &changes.files {
+        if let Some(original) = &file.original {
+            let path = file.path.strip_prefix(".").unwrap_or(&file.path);
+            let name = PathBuf::from(format!("{}:{}", changes.base, path.display()));
+            rendered.push_str(&format.render_file(&name, original));
+            rendered.push('\n');
+        }
+    }
+    rendered
+}
+
+/// Lists every file under `root` that a section's rules matched, and whether it was
+/// included or excluded, without reading anything.
+fn list_files(
+    root: &Path,
+    sections: &[Section],
+    options: &Options,
+) -> Result<Vec<Vec<(PathBuf, Selection)>>, Error> {
+    let paths = walk::files(root, options.ignore_mode, options.max_depth, options.hidden)
+        .map_err(Error::Walk)?;
+    sections
+        .iter()
+        .map(|section| {
+            let selector =
+                Selector::new(section.include, section.exclude).map_err(Error::Pattern)?;
+            Ok(paths
+                .iter()
+                .map(|path| (path.clone(), selector.select(path)))
+                .filter(|(_, selection)| *selection != Selection::Unmatched)
+                .collect())
+        })
+        .collect()
+}
+
+/// The walked files that the section selects, limited to the changed files in git
+/// mode and, for Rust files, to those reachable from the entry. A section whose globs
+/// match none of the walked files, likely a mistyped pattern, is recorded in `notes`.
+fn select_files<'p>(
+    section: &Section,
+    paths: &'p [PathBuf],
+    pipeline: &Pipeline,
+    notes: &mut Notes,
+) -> Result<Vec<&'p PathBuf>, Error> {
+    #[cfg(feature = "logging")]
+    info!(
+        "Starting to process files including {:?} and excluding {:?}",
+        section.include, section.exclude
+    );
+
+    let selector = Selector::new(section.include, section.exclude).map_err(Error::Pattern)?;
+    let matched: Vec<&PathBuf> = paths
+        .iter()
+        .filter(|path| {
+            let selection = selector.select(path);
+            #[cfg(feature = "logging")]
+            info!("Processing file: {} ({selection})", path.to_string_lossy());
+            selection.is_included()
+        })
+        .collect();
+    if matched.is_empty() && !section.include.is_empty() {
+        warn!(
+            "Section [{}] matched no files with {:?}",
+            section.name, section.include
+        );
+        notes.unmatched.push(section.name.to_string());
+    }
+    Ok(matched
+        .into_iter()
+        .filter(|path| {
+            pipeline
+                .changes
+                .as_ref()
+                .is_none_or(|changes| changes.contains(path))
+                && pipeline.reachable.as_ref().is_none_or(|reachable| {
+                    reachable.contains(*path)
+                        || path.extension().is_none_or(|extension| extension != "rs")
+                })
+        })
+        .collect())
+}
+
+/// Reads every file the section selects. Binary files are replaced by a placeholder
+/// line, and they and unreadable files are recorded in `notes`. When `ranked`, their
+/// terms are indexed for the query as well.
+///
+/// Every file is kept until the end, as the budget, the query and the header need
+/// them all; [`stream_prompt`] writes them as they come instead.
+fn collect_files(
+    section: &Section,
+    paths: &[PathBuf],
+    pipeline: &Pipeline,
+    ranked: bool,
+    notes: &mut Notes,
+) -> Result<Vec<SourceFile>, Error> {
+    let selected = select_files(section, paths, pipeline, notes)?;
+    let mut files = Vec::new();
+    read_files(&selected, pipeline, ranked, notes, |file| {
+        files.push(file);
+        Ok(())
+    })?;
+    Ok(files)
+}
+
+/// Reads and transforms `paths` in parallel, a bounded batch at a time, and hands each
+/// file to `take` in path order so the prompt is the same on every run.
+fn read_files(
+    paths: &[&PathBuf],
+    pipeline: &Pipeline,
+    ranked: bool,
+    notes: &mut Notes,
+    mut take: impl FnMut(SourceFile) -> Result<(), Error>,
+) -> Result<(), Error> {
+    for batch in paths.chunks(rayon::current_num_threads() * READ_AHEAD) {
+        let read: Vec<(Option<SourceFile>, Notes)> = batch
+            .par_iter()
+            .map(|path| read_file(pipeline, path, ranked))
+            .collect();
+        for (file, file_notes) in read {
+            notes.skipped.extend(file_notes.skipped);
+            notes.minified.extend(file_notes.minified);
+            notes.capped.extend(file_notes.capped);
+            notes.secrets.extend(file_notes.secrets);
+            notes.documents.extend(file_notes.documents);
+            if let Some(file) = file {
+                take(file)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Reads, transforms and counts one file, or returns `None` if it cannot be read.
+/// When `ranked`, also indexes the terms of the full text for the query.
+fn read_file(pipeline: &Pipeline, file_path: &Path, ranked: bool) -> (Option<SourceFile>, Notes) {
+    let options = pipeline.options;
+    let mut notes = Notes::default();
+    let contents = match content::read(&pipeline.root.join(file_path)) {
+        Ok(Content::Text { text, encoding }) => {
+            #[cfg(feature = "logging")]
+            if let Some(encoding) = encoding {
+                info!(
+                    "Transcoded [{}] from {encoding}",
+                    file_path.to_string_lossy()
+                );
+            }
+            #[cfg(not(feature = "logging"))]
+            let _ = encoding;
+            let text = match &pipeline.secrets {
+                Some(scanner) => {
+                    let (redacted, findings) = scanner.redact(file_path, &text);
+                    for finding in &findings {
+                        warn!(
+                            "Redacted a secret in [{}] ({})",
+                            file_path.to_string_lossy(),
+                            finding.kind
+                        );
+                    }
+                    notes.secrets.extend(findings);
+                    redacted
+                }
+                None => text,
+            };
+            if ranked {
+                notes.documents.push(rank::Document::new(file_path, &text));
+            }
+            let text = transform(pipeline, file_path, text, &mut notes);
+            cap_file(pipeline, file_path, text, &mut notes)
+        }
+        Ok(Content::Binary { size, mime }) => {
+            warn!(
+                "Skipping binary file [{}] ({mime})",
+                file_path.to_string_lossy()
+            );
+            let placeholder = Content::placeholder(size, &mime);
+            notes.skipped.push(SkippedFile {
+                path: file_path.to_path_buf(),
+                reason: SkipReason::Binary { size, mime },
+            });
+            placeholder
+        }
+        Err(e) => {
+            if e.kind() == std::io::ErrorKind::PermissionDenied {
+                warn!(
+                    "Warning: [{}] permission denied error.",
+                    file_path.to_string_lossy()
+                );
+            } else {
+                warn!(
+                    "Error reading file [{}]: {}",
+                    file_path.to_string_lossy(),
+                    e
+                );
+            }
+            notes.skipped.push(SkippedFile {
+                path: file_path.to_path_buf(),
+                reason: SkipReason::Unreadable(e.to_string()),
+            });
+            return (None, notes);
+        }
+    };
+
+    let mut file = SourceFile {
+        path: file_path.to_path_buf(),
+        contents,
+        tokens: 0,
+        line_range: None,
+    };
+    file.tokens = options.tokenizer.count(&file.render(options.format));
+    #[cfg(feature = "logging")]
+    info!(
+        "Counted {} tokens in [{}]",
+        file.tokens,
+        file.path.to_string_lossy()
+    );
+    (Some(file), notes)
 }
@@ -0,0 +1,315 @@
+use crate::select::Selector;
+use crate::Tokenizer;
+use serde::{Deserialize, Serialize};
+use std::fmt::{self, Write as _};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// The most a single file may hold before its middle is cut out.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum FileLimit {
+    Bytes(usize),
+    Lines(usize),
+    Tokens(usize),
+}
+
+impl FileLimit {
+    const fn value(self) -> usize {
+        match self {
+            Self::Bytes(limit) | Self::Lines(limit) | Self::Tokens(limit) => limit,
+        }
+    }
+
+    fn measure(self, line: &str, tokenizer: &Tokenizer) -> usize {
+        match self {
+            Self::Bytes(_) => line.len(),
+            Self::Lines(_) => 1,
+            Self::Tokens(_) => tokenizer.count(line),
+        }
+    }
+}
+
+/// Parses a number followed by a unit: `b`, `kb`, `mb`, `lines` or `tokens`, such as
+/// `64kb` or `2000 lines`. Kilobytes and megabytes are multiples of 1024.
+impl FromStr for FileLimit {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, String> {
+        let invalid = || {
+            format!("Invalid file limit [{text}]: expected a number followed by b, kb, mb, lines or tokens")
+        };
+        let trimmed = text.trim();
+        let (number, unit) = trimmed.split_at(
+            trimmed
+                .find(|c: char| !c.is_ascii_digit())
+                .unwrap_or(trimmed.len()),
+        );
+        let number: usize = number.parse().map_err(|_| invalid())?;
+        match unit.trim().to_ascii_lowercase().as_str() {
+            "b" | "bytes" => Ok(Self::Bytes(number)),
+            "kb" => Ok(Self::Bytes(number.saturating_mul(1024))),
+            "mb" => Ok(Self::Bytes(number.saturating_mul(1024 * 1024))),
+            "lines" => Ok(Self::Lines(number)),
+            "tokens" => Ok(Self::Tokens(number)),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+impl TryFrom<String> for FileLimit {
+    type Error = String;
+
+    fn try_from(text: String) -> Result<Self, String> {
+        text.parse()
+    }
+}
+
+impl From<FileLimit> for String {
+    fn from(limit: FileLimit) -> Self {
+        limit.to_string()
+    }
+}
+
+impl fmt::Display for FileLimit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Bytes(limit) => write!(f, "{limit} bytes"),
+            Self::Lines(limit) => write!(f, "{limit} lines"),
+            Self::Tokens(limit) => write!(f, "{limit} tokens"),
+        }
+    }
+}
+
+/// The limit of the files matching a gitignore-style glob, written `glob=limit`, or
+/// `glob=none` to lift the limit.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct LimitRule {
+    pub glob: String,
+    pub limit: Option<FileLimit>,
+}
+
+impl FromStr for LimitRule {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, String> {
+        let (glob, limit) = text
+            .rsplit_once('=')
+            .ok_or_else(|| format!("Invalid file limit [{text}]: expected glob=limit"))?;
+        let limit = if limit.trim().eq_ignore_ascii_case("none") {
+            None
+        } else {
+            Some(limit.parse()?)
+        };
+        Ok(Self {
+            glob: glob.trim().to_string(),
+            limit,
+        })
+    }
+}
+
+impl TryFrom<String> for LimitRule {
+    type Error = String;
+
+    fn try_from(text: String) -> Result<Self, String> {
+        text.parse()
+    }
+}
+
+impl From<LimitRule> for String {
+    fn from(rule: LimitRule) -> Self {
+        rule.to_string()
+    }
+}
+
+impl fmt::Display for LimitRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.limit {
+            Some(limit) => write!(f, "{}={limit}", self.glob),
+            None => write!(f, "{}=none", self.glob),
+        }
+    }
+}
+
+/// How large each file may be: a limit for every file, and rules overriding it for
+/// the files matching their glob, the last matching rule winning.
+#[derive(Clone, Debug, Default)]
+pub struct FileLimits {
+    pub default: Option<FileLimit>,
+    pub rules: Vec<LimitRule>,
+}
+
+/// [`FileLimits`] with their globs compiled.
+pub(crate) struct Limits {
+    default: Option<FileLimit>,
+    rules: Vec<(Selector, Option<FileLimit>)>,
+}
+
+impl Limits {
+    pub(crate) fn new(limits: &FileLimits) -> Result<Self, String> {
+        let rules = limits
+            .rules
+            .iter()
+            .map(|rule| {
+                Ok((
+                    Selector::new(std::slice::from_ref(&rule.glob), &[])?,
+                    rule.limit,
+                ))
+            })
+            .collect::<Result<_, String>>()?;
+        Ok(Self {
+            default: limits.default,
+            rules,
+        })
+    }
+
+    /// The limit of the file at `path`, if it has one.
+    pub(crate) fn of(&self, path: &Path) -> Option<FileLimit> {
+        self.rules
+            .iter()
+            .rev()
+            .find(|(selector, _)| selector.select(path).is_included())
+            .map_or(self.default, |(_, limit)| *limit)
+    }
+}
+
+/// A file cut to its head and tail to stay under its limit.
+pub struct CappedFile {
+    pub path: PathBuf,
+    pub limit: FileLimit,
+    /// Lines of the file as written before the cut.
+    pub lines: usize,
+    pub omitted: usize,
+}
+
+impl fmt::Display for CappedFile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}  ({} of {} lines omitted, limit {})",
+            self.path.display(),
+            self.omitted,
+            self.lines,
+            self.limit
+        )
+    }
+}
+
+/// Cuts `text` to the whole lines at its head and tail that fit in `limit`.
+///
+/// The head takes up to half of the limit and the tail the rest, and the lines in
+/// between become a `[... N lines omitted ...]` marker. Returns the cut text and how
+/// many lines were omitted, or `None` if `text` fits.
+#[must_use]
+pub fn cap(text: &str, limit: FileLimit, tokenizer: &Tokenizer) -> Option<(String, usize)> {
+    // A token is at least one byte, so a text no longer than the limit in bytes fits.
+    if !matches!(limit, FileLimit::Lines(_)) && text.len() <= limit.value() {
+        return None;
+    }
+    let lines: Vec<&str> = text.split_inclusive('\n').collect();
+    let sizes: Vec<usize> = lines
+        .iter()
+        .map(|line| limit.measure(line, tokenizer))
+        .collect();
+    if sizes.iter().sum::<usize>() <= limit.value() {
+        return None;
+    }
+
+    let (mut head, mut used) = (0, 0);
+    while head < lines.len() && used + sizes[head] <= limit.value() / 2 {
+        used += sizes[head];
+        head += 1;
+    }
+    let budget = limit.value() - used;
+    let (mut tail, mut used) = (lines.len(), 0);
+    while tail > head && used + sizes[tail - 1] <= budget {
+        used += sizes[tail - 1];
+        tail -= 1;
+    }
+    let omitted = tail - head;
+    let tail_text = lines[tail..].concat();
+    let mut capped = lines[..head].concat();
+    let _ = write!(capped, "[... {omitted} lines omitted ...]");
+    if !tail_text.is_empty() || text.ends_with('\n') {
+        capped.push('\n');
+    }
+    capped.push_str(&tail_text);
+    Some((capped, omitted))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn limits_parse_with_their_unit() {
+        assert_eq!("64kb".parse(), Ok(FileLimit::Bytes(64 * 1024)));
+        assert_eq!("2000 lines".parse(), Ok(FileLimit::Lines(2000)));
+        assert_eq!(" 500 Tokens ".parse(), Ok(FileLimit::Tokens(500)));
+        assert!("lines".parse::<FileLimit>().is_err());
+        assert!("10 pages".parse::<FileLimit>().is_err());
+    }
+
+    #[test]
+    fn rules_parse_a_glob_and_a_limit_or_none() {
+        assert_eq!(
+            "*.lock=none".parse(),
+            Ok(LimitRule {
+                glob: "*.lock".to_string(),
+                limit: None,
+            })
+        );
+        let rule: LimitRule = "src/**=100 lines".parse().unwrap();
+        assert_eq!(rule.limit, Some(FileLimit::Lines(100)));
+        assert_eq!(rule.to_string(), "src/**=100 lines");
+        assert!("*.lock".parse::<LimitRule>().is_err());
+    }
+
+    #[test]
+    fn the_last_matching_rule_wins() {
+        let limits = Limits::new(&FileLimits {
+            default: Some(FileLimit::Lines(100)),
+            rules: vec![
+                "*.rs=10 lines".parse().unwrap(),
+                "src/gen/=none".parse().unwrap(),
+            ],
+        })
+        .unwrap();
+        assert_eq!(
+            limits.of(Path::new("./README.md")),
+            Some(FileLimit::Lines(100))
+        );
+        assert_eq!(
+            limits.of(Path::new("./src/main.rs")),
+            Some(FileLimit::Lines(10))
+        );
+        assert_eq!(limits.of(Path::new("./src/gen/api.rs")), None);
+    }
+
+    #[test]
+    fn cap_keeps_the_head_and_tail() {
+        let tokenizer = Tokenizer::default();
+        let mut text = String::new();
+        for line in 1..=10 {
+            let _ = writeln!(text, "line {line}");
+        }
+        let (capped, omitted) = cap(&text, FileLimit::Lines(4), &tokenizer).unwrap();
+        assert_eq!(omitted, 6);
+        assert_eq!(
+            capped,
+            "line 1\nline 2\n[... 6 lines omitted ...]\nline 9\nline 10\n"
+        );
+        assert_eq!(cap(&text, FileLimit::Lines(10), &tokenizer), None);
+        assert_eq!(cap(&text, FileLimit::Bytes(text.len()), &tokenizer), None);
+    }
+
+    #[test]
+    fn cap_by_bytes_counts_whole_lines() {
+        let tokenizer = Tokenizer::default();
+        let (capped, omitted) =
+            cap("aaaa\nbbbb\ncccc\ndddd", FileLimit::Bytes(10), &tokenizer).unwrap();
+        assert_eq!(omitted, 2);
+        assert_eq!(capped, "aaaa\n[... 2 lines omitted ...]\ndddd");
+    }
+}
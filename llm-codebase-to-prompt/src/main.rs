@@ -1,22 +1,40 @@
-use clap::{arg, command, Parser};
-use llm_codebase_to_prompt::process_files;
-use notify::{RecommendedWatcher, RecursiveMode, Watcher};
-use std::fs::File;
-use std::path::{Path, PathBuf};
-use std::sync::mpsc::channel;
-use std::env;
-use log::error;
+use clap::Parser;
 use env_logger::Builder;
-use std::io::Write;
+use llm_codebase_to_prompt::config::CONFIG_FILE;
+use llm_codebase_to_prompt::{
+    watch, ChangeSource, ChunkLimit, Config, DataOptions, DeltaOptions, EntryOptions, FileLimit,
+    FileLimits, GitOptions, IgnoreMode, LimitRule, MinifyOptions, MinifyStage, Options,
+    OutputFormat, OverBudget, Profile, PromptBuilder, Report, SecretOptions, SectionConfig,
+    Tokenizer, TreeAnnotation, TreeOptions,
+};
+use log::error;
+use std::fs;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::time::Duration;
 
 #[derive(Parser)]
+#[allow(clippy::struct_excessive_bools)]
 #[command(name = "llm-codebase-to-prompt", version, about, long_about = None)]
 struct Cli {
-    #[arg(long, required = true)]
-    source_files: String,
+    /// Gitignore-style glob selecting source files. Repeatable; `!pattern` negates.
+    /// Replaces the globs of the profile's `source` section.
+    #[arg(long, visible_alias = "source-include")]
+    source_files: Vec<String>,
+
+    /// Glob removing files from the source section. Repeatable.
+    #[arg(long)]
+    source_exclude: Vec<String>,
 
-    #[arg(long, required = true)]
-    instruct_files: String,
+    /// Gitignore-style glob selecting instruction files. Repeatable; `!pattern` negates.
+    /// Replaces the globs of the profile's `instruct` section.
+    #[arg(long, visible_alias = "instruct-include")]
+    instruct_files: Vec<String>,
+
+    /// Glob removing files from the instruct section. Repeatable.
+    #[arg(long)]
+    instruct_exclude: Vec<String>,
 
     #[arg(long)]
     source_context: Option<String>,
@@ -24,103 +42,742 @@ struct Cli {
     #[arg(long)]
     instruct_context: Option<String>,
 
+    /// Which ignore files to honor (`--gitignore=none|root|recursive`): none, only the
+    /// root ones, or every nested one.
+    /// Also reads `.promptignore` files alongside `.gitignore`.
+    #[arg(long, value_enum, num_args = 0..=1, require_equals = true, default_missing_value = "recursive")]
+    gitignore: Option<IgnoreMode>,
+
+    /// Only honor the ignore files at the root of the working directory.
+    /// Shorthand for `--gitignore=root`.
+    #[arg(long, conflicts_with = "gitignore")]
+    no_recursive_gitignore: bool,
+
+    /// How many directory levels to descend; 1 only reads the working directory itself.
     #[arg(long)]
-    gitignore: bool,
+    max_depth: Option<usize>,
 
+    /// Also read hidden files and directories, such as `.env` or `.github`; `.git` is
+    /// always skipped.
     #[arg(long)]
-    no_recursive_gitignore: bool,
+    hidden: bool,
 
+    /// Rebuild the prompt whenever a file it is made of changes.
     #[arg(long)]
     watch: bool,
 
-    #[arg(required = true)]
+    /// How long, in milliseconds, the files must stay unchanged before a rebuild.
+    #[arg(long, default_value_t = 300)]
+    debounce: u64,
+
+    /// How each file is laid out in the prompt [default: plain].
+    #[arg(long, value_enum)]
+    format: Option<OutputFormat>,
+
+    /// Built-in encoding (`cl100k_base`, `o200k_base`, `p50k_base`, `r50k_base`) or a local
+    /// tokenizer.json / tiktoken BPE file used to count tokens [default: `cl100k_base`].
+    #[arg(long)]
+    tokenizer: Option<String>,
+
+    /// Maximum size of the whole prompt, in tokens.
+    #[arg(long)]
+    max_tokens: Option<usize>,
+
+    /// What to do when the prompt is larger than --max-tokens [default: fail].
+    #[arg(long, value_enum)]
+    over_budget: Option<OverBudget>,
+
+    /// Where to write the prompt, relative to the current directory; `-` writes it to
+    /// stdout and status messages to stderr [default: prompt.txt].
+    #[arg(long)]
+    output: Option<PathBuf>,
+
+    /// Split the prompt into prompt.001.txt, prompt.002.txt, ... of at most this many tokens.
+    /// A file split across parts is labeled with the lines each part holds, counted in
+    /// the file as written, after `--outline` and `--minify`.
+    #[arg(long, conflicts_with = "chunk_bytes")]
+    chunk_tokens: Option<usize>,
+
+    /// Split the prompt into prompt.001.txt, prompt.002.txt, ... of at most this many bytes.
+    #[arg(long)]
+    chunk_bytes: Option<usize>,
+
+    /// Print the token count of every file in the prompt.
+    #[arg(long)]
+    token_report: bool,
+
+    /// Draw the project layout at the top of the prompt.
+    #[arg(long)]
+    tree: bool,
+
+    /// How many directory levels the tree shows before collapsing directories.
+    #[arg(long, requires = "tree")]
+    tree_depth: Option<usize>,
+
+    /// Also list files whose contents are not in the prompt, marked as such.
+    #[arg(long, requires = "tree")]
+    tree_show_excluded: bool,
+
+    /// Details to show next to each file in the tree, comma separated.
+    #[arg(long, value_enum, value_delimiter = ',', requires = "tree")]
+    tree_annotate: Vec<TreeAnnotation>,
+
+    /// Only include files changed in a git revision range, such as `main..HEAD`,
+    /// `main...HEAD` (since the merge base) or `HEAD` (against the working tree).
+    #[arg(long, conflicts_with = "staged")]
+    changed: Option<String>,
+
+    /// Only include files with staged changes.
+    #[arg(long)]
+    staged: bool,
+
+    /// Add the unified diff of the changes.
+    #[arg(long)]
+    diff: bool,
+
+    /// Add the version of each changed file from before the change.
+    #[arg(long)]
+    include_original: bool,
+
+    /// Gitignore-style glob of Rust files to reduce to an outline: function and
+    /// `macro_rules!` bodies become `{ ... }`, `const` and `static` values `...`, and
+    /// comments other than doc comments are dropped. Repeatable; other files keep
+    /// their full contents.
+    #[arg(long)]
+    outline: Vec<String>,
+
+    /// Minify file contents to save tokens: `--minify` runs every stage, or pick some
+    /// with `--minify=license,comments,blank-lines,indent`.
+    #[arg(long, value_enum, value_delimiter = ',', num_args = 0.., require_equals = true, default_missing_value = "all")]
+    minify: Option<Vec<MinifyStage>>,
+
+    /// Keep documentation comments such as `///` when minifying comments away.
+    #[arg(long, requires = "minify")]
+    keep_doc_comments: bool,
+
+    /// Leave secrets in the prompt instead of redacting keys, tokens, private keys,
+    /// high-entropy strings and `.env` values.
+    #[arg(long, conflicts_with_all = ["secret_pattern", "fail_on_secrets"])]
+    allow_secrets: bool,
+
+    /// Regular expression of an extra secret to redact; with a capture group, only the
+    /// group is redacted. Repeatable.
+    #[arg(long)]
+    secret_pattern: Vec<String>,
+
+    /// Fail instead of writing a prompt when a secret is found.
+    #[arg(long)]
+    fail_on_secrets: bool,
+
+    /// Rank the source files against this query by the words of their paths,
+    /// identifiers and comments, and include only those that match, best first, as
+    /// many as fit `--max-tokens`.
+    #[arg(long)]
+    query: Option<String>,
+
+    /// Only include the Rust files reachable from this file, relative to the working
+    /// directory, through `mod` declarations and `crate::`, `self::` and `super::`
+    /// paths. Other files are selected as usual.
+    #[arg(long)]
+    entry: Option<PathBuf>,
+
+    /// How many `mod` or `use` steps to follow from `--entry`.
+    #[arg(long, requires = "entry")]
+    entry_depth: Option<usize>,
+
+    /// Also include the modules that use `--entry`, directly or through each other.
+    #[arg(long, requires = "entry")]
+    entry_dependents: bool,
+
+    /// Prefix each line of every file with its number, as `  42 | code`. Numbers
+    /// refer to the file on disk, even after `--outline` or `--minify`.
+    #[arg(long)]
+    line_numbers: bool,
+
+    /// The most any file may hold, such as `64kb`, `2000lines` or `8000tokens`. Larger
+    /// files keep the lines at their head and tail, with a `[... N lines omitted ...]`
+    /// marker in between.
+    #[arg(long)]
+    max_file_size: Option<FileLimit>,
+
+    /// Limit for the files matching a glob, as `glob=limit`, or `glob=none` to lift it,
+    /// such as `--file-limit 'generated/**=200lines'`. Repeatable; the last matching
+    /// one wins over `--max-file-size`.
+    #[arg(long)]
+    file_limit: Vec<LimitRule>,
+
+    /// Jinja-like template laying out the whole prompt, in place of the files of each
+    /// section followed by its context. It sees `sections`, `files`, `tree`, `git`,
+    /// `header`, `total_tokens` and `format`, e.g.
+    /// `{% for s in sections %}{{ s.context }}{{ s.rendered }}{% endfor %}`.
+    #[arg(long)]
+    template: Option<PathBuf>,
+
+    /// Keep notebooks and CSV, TSV and JSON data files as they are, instead of
+    /// rendering notebooks as cells and large data files as a schema and sample.
+    #[arg(long, conflicts_with_all = ["data_sample", "data_min_bytes", "notebook_outputs"])]
+    raw_data: bool,
+
+    /// How many rows of a table, or elements of a JSON array, a data file shows.
+    #[arg(long, default_value_t = 5)]
+    data_sample: usize,
+
+    /// Size in bytes from which CSV, TSV and JSON files are summarized; smaller ones,
+    /// such as `package.json`, are kept whole.
+    #[arg(long, default_value_t = 32 * 1024)]
+    data_min_bytes: usize,
+
+    /// Keep the text outputs of notebook cells.
+    #[arg(long)]
+    notebook_outputs: bool,
+
+    /// Only write the files added or modified since the last prompt, under a note
+    /// listing what changed, including removed files. Prompts written with this flag
+    /// record what they held in a hidden manifest next to them; until there is one,
+    /// the whole prompt is written.
+    #[arg(long)]
+    since_last: bool,
+
+    /// Show modified files as unified diffs against the version in the last prompt.
+    #[arg(long, requires = "since_last")]
+    since_last_diffs: bool,
+
+    /// Open the prompt with its files, lines and tokens per language, its largest files
+    /// and its total tokens.
+    #[arg(long)]
+    stats_header: bool,
+
+    /// Print the files, lines and tokens per language, the largest files and the total
+    /// tokens the prompt would take, before any `--max-tokens` budget, instead of
+    /// writing it.
+    #[arg(long, conflicts_with = "dry_run")]
+    stats: bool,
+
+    /// List which rule included or excluded each file instead of writing a prompt.
+    #[arg(long)]
+    dry_run: bool,
+
+    /// Named profile from `llm-prompt.toml` in the working directory. Without it, the
+    /// `default` profile is used if the file defines one. Flags override its values.
+    #[arg(long)]
+    profile: Option<String>,
+
+    /// Print the profile merged with the flags, as TOML, instead of writing a prompt.
+    #[arg(long)]
+    print_config: bool,
+
+    #[arg(default_value = ".")]
     working_directory: PathBuf,
 }
 
-fn main() {
+const DEFAULT_TOKENIZER: &str = "cl100k_base";
+
+/// The `--output` path that means stdout.
+const STDOUT: &str = "-";
+
+/// Prints a progress message to stdout, or to stderr when the prompt itself goes to stdout.
+macro_rules! status {
+    ($profile:expr, $($arg:tt)*) => {
+        if is_stdout($profile) {
+            eprintln!($($arg)*);
+        } else {
+            println!($($arg)*);
+        }
+    };
+}
+
+/// Logs every run to `prompt.log` in the current directory.
+#[cfg(feature = "logging")]
+fn init_logging() {
+    use std::fs::OpenOptions;
+    let log_file = OpenOptions::new()
+        .create(true)
+        .write(true)
+        .truncate(true)
+        .open("prompt.log")
+        .expect("Unable to open log file");
+    Builder::new()
+        .format(move |buf, record| {
+            writeln!(
+                buf,
+                "{} [{}] - {}",
+                chrono::Local::now().format("%Y-%m-%dT%H:%M:%S"),
+                record.level(),
+                record.args()
+            )
+        })
+        .target(env_logger::Target::Pipe(Box::new(log_file)))
+        .filter_level(log::LevelFilter::Debug)
+        .init();
+}
+
+fn main() -> ExitCode {
     #[cfg(feature = "logging")]
-    {
-        use std::fs::OpenOptions;
-        let log_file = OpenOptions::new()
-            .create(true)
-            .write(true)
-            .truncate(true)
-            .open("prompt.log")
-            .expect("Unable to open log file");
-        Builder::new()
-            .format(move |buf, record| {
-                writeln!(buf, "{} [{}] - {}", chrono::Local::now().format("%Y-%m-%dT%H:%M:%S"), record.level(), record.args())
-            })
-            .target(env_logger::Target::Pipe(Box::new(log_file)))
-            .filter_level(log::LevelFilter::Debug)
-            .init();
-    }
+    init_logging();
 
     let args = Cli::parse();
 
-    let original_dir = env::current_dir().expect("Failed to get current directory");
+    let profile = match effective_profile(&args) {
+        Ok(profile) => profile,
+        Err(e) => return fail("Error reading configuration", &e),
+    };
+    if args.print_config {
+        return match profile.to_toml() {
+            Ok(toml) => {
+                print!("{toml}");
+                ExitCode::SUCCESS
+            }
+            Err(e) => fail("Error printing configuration", &e),
+        };
+    }
 
-    if let Err(e) = env::set_current_dir(&args.working_directory) {
-        println!("Error changing working directory: {}", e);
-        #[cfg(feature = "logging")]
-        error!("Error changing working directory: {}", e);
-        return;
+    let tokenizer = match Tokenizer::load(profile.tokenizer.as_deref().unwrap_or(DEFAULT_TOKENIZER))
+    {
+        Ok(tokenizer) => tokenizer,
+        Err(e) => return fail("Error loading tokenizer", &e),
+    };
+    let template = profile.template.as_ref().map(|path| {
+        let path = args.working_directory.join(path);
+        fs::read_to_string(&path).map_err(|e| format!("{}: {e}", path.display()))
+    });
+    let template = match template.transpose() {
+        Ok(template) => template,
+        Err(e) => return fail("Error reading template", &e),
+    };
+    let mut options = options(&args, &profile, tokenizer);
+    options.template = template;
+    if args.stats {
+        options.max_tokens = None;
+    }
+    let builder = PromptBuilder::new(&args.working_directory)
+        .sections(profile.sections.iter().cloned())
+        .options(options);
+
+    if args.dry_run {
+        return match print_selection(&profile, &builder) {
+            Ok(()) => ExitCode::SUCCESS,
+            Err(e) => fail("Error listing files", &e.to_string()),
+        };
     }
 
+    if args.stats {
+        return match builder.build() {
+            Ok(prompt) => {
+                print!("{}", prompt.report.stats);
+                ExitCode::SUCCESS
+            }
+            Err(e) => fail("Error counting files", &e.to_string()),
+        };
+    }
+
+    if let Err(e) = create_prompt(&args, &profile, &builder) {
+        let code = fail("Error creating prompt", &e);
+        if !args.watch {
+            return code;
+        }
+    }
     if args.watch {
-        let (tx, rx) = channel();
-        let mut watcher: RecommendedWatcher = notify::recommended_watcher(tx).expect("Failed to create watcher");
-
-        // Add paths to watch
-        let source_path = Path::new(&args.source_files);
-        let instruct_path = Path::new(&args.instruct_files);
-
-        watcher.watch(source_path, RecursiveMode::Recursive).expect("Failed to watch source files");
-        watcher.watch(instruct_path, RecursiveMode::Recursive).expect("Failed to watch instruct files");
-
-        loop {
-            match rx.recv() {
-                Ok(event) => {
-                    println!("File changed: {:?}", event);
-                    if let Err(e) = create_prompt(&args, &original_dir) {
-                        println!("Error: {}", e);
-                        #[cfg(feature = "logging")]
-                        error!("Error creating prompt: {}", e);
-                    }
-                }
-                Err(e) => {
-                    println!("Watch error: {:?}", e);
-                    #[cfg(feature = "logging")]
-                    error!("Watch error: {:?}", e);
-                },
+        let rebuild = |changed: &[PathBuf]| {
+            let changed: Vec<_> = changed
+                .iter()
+                .map(|path| path.display().to_string())
+                .collect();
+            status!(
+                &profile,
+                "Rebuilding after changes to {}",
+                changed.join(", ")
+            );
+            if let Err(e) = create_prompt(&args, &profile, &builder) {
+                fail("Error creating prompt", &e);
+            }
+        };
+        let output = absolute(&output_path(&profile, builder.root()));
+        let log = absolute(Path::new("prompt.log"));
+        let manifest = absolute(&manifest_path(&profile, builder.root()));
+        let staging = staging_path(&output);
+        let skip = |path: &Path| {
+            path == log || path == manifest || path == staging || is_output(path, &output)
+        };
+        if let Err(e) = watch::watch(
+            &builder,
+            Duration::from_millis(args.debounce),
+            skip,
+            rebuild,
+        ) {
+            return fail("Error watching files", &e.to_string());
+        }
+    }
+    ExitCode::SUCCESS
+}
+
+/// Reports a failure on stderr and in the log.
+fn fail(context: &str, e: &str) -> ExitCode {
+    eprintln!("{context}: {e}");
+    #[cfg(feature = "logging")]
+    error!("{context}: {e}");
+    ExitCode::FAILURE
+}
+
+/// Whether the prompt is written to stdout, leaving stderr for everything else.
+fn is_stdout(profile: &Profile) -> bool {
+    profile.output.as_deref() == Some(Path::new(STDOUT))
+}
+
+fn options(args: &Cli, profile: &Profile, tokenizer: Tokenizer) -> Options {
+    Options {
+        ignore_mode: profile.gitignore.unwrap_or_default(),
+        max_depth: profile.max_depth,
+        hidden: profile.hidden.unwrap_or_default(),
+        format: profile.format.unwrap_or_default(),
+        tokenizer,
+        max_tokens: profile.max_tokens,
+        over_budget: profile.over_budget.unwrap_or_default(),
+        tree: args.tree.then(|| TreeOptions {
+            depth: args.tree_depth,
+            show_excluded: args.tree_show_excluded,
+            annotations: args.tree_annotate.clone(),
+        }),
+        git: change_source(args).map(|source| GitOptions {
+            source,
+            diff: args.diff,
+            original: args.include_original,
+        }),
+        outline: args.outline.clone(),
+        minify: args
+            .minify
+            .as_deref()
+            .map(|stages| MinifyOptions::from_stages(stages, args.keep_doc_comments)),
+        secrets: (!args.allow_secrets).then(|| SecretOptions {
+            patterns: args.secret_pattern.clone(),
+            fail: args.fail_on_secrets,
+        }),
+        query: args.query.clone(),
+        entry: args.entry.clone().map(|path| EntryOptions {
+            path,
+            depth: args.entry_depth,
+            dependents: args.entry_dependents,
+        }),
+        line_numbers: args.line_numbers,
+        data: (!args.raw_data).then_some(DataOptions {
+            sample: args.data_sample,
+            notebook_outputs: args.notebook_outputs,
+            min_bytes: args.data_min_bytes,
+        }),
+        template: None,
+        since_last: args.since_last.then(|| DeltaOptions {
+            manifest: manifest_path(profile, &args.working_directory),
+            diffs: args.since_last_diffs,
+        }),
+        file_limits: FileLimits {
+            default: profile.max_file_size,
+            rules: profile.file_limits.clone(),
+        },
+        stats: args.stats_header,
+    }
+}
+
+fn change_source(args: &Cli) -> Option<ChangeSource> {
+    if args.staged {
+        Some(ChangeSource::Staged)
+    } else {
+        args.changed.clone().map(ChangeSource::Range)
+    }
+}
+
+/// The profile from `llm-prompt.toml` with the command line flags layered on top,
+/// and every section given a context.
+fn effective_profile(args: &Cli) -> Result<Profile, String> {
+    let profile = match (Config::load(&args.working_directory)?, &args.profile) {
+        (Some(config), name) => config.profile(name.as_deref())?,
+        (None, Some(name)) => {
+            return Err(format!(
+                "Cannot use profile [{name}], there is no {CONFIG_FILE} in {}",
+                args.working_directory.display()
+            ))
+        }
+        (None, None) => Profile::default(),
+    };
+
+    let mut profile = profile.merge(flags_profile(args));
+    for section in &mut profile.sections {
+        if section.context.is_none() {
+            section.context = Some(default_context(&section.name));
+        }
+    }
+    if profile
+        .sections
+        .iter()
+        .all(|section| section.include.is_empty())
+    {
+        return Err("No files selected: pass --source-files and --instruct-files, or a --profile with sections".to_string());
+    }
+    if is_stdout(&profile) && (args.chunk_tokens.is_some() || args.chunk_bytes.is_some()) {
+        return Err("A prompt written to stdout cannot be split into parts".to_string());
+    }
+    if profile.template.is_some() && (args.chunk_tokens.is_some() || args.chunk_bytes.is_some()) {
+        return Err("A prompt laid out by a template cannot be split into parts".to_string());
+    }
+    Ok(profile)
+}
+
+/// The values set on the command line, as a profile to layer over the configured one.
+fn flags_profile(args: &Cli) -> Profile {
+    let sections = [
+        (
+            "source",
+            &args.source_files,
+            &args.source_exclude,
+            &args.source_context,
+        ),
+        (
+            "instruct",
+            &args.instruct_files,
+            &args.instruct_exclude,
+            &args.instruct_context,
+        ),
+    ]
+    .into_iter()
+    .filter(|(_, include, exclude, context)| {
+        !include.is_empty() || !exclude.is_empty() || context.is_some()
+    })
+    .map(|(name, include, exclude, context)| SectionConfig {
+        name: name.to_string(),
+        include: include.clone(),
+        exclude: exclude.clone(),
+        context: context.clone(),
+    })
+    .collect();
+
+    Profile {
+        // Relative to where the tool was started, unlike the profile's which is relative
+        // to the working directory.
+        output: args.output.as_ref().map(|output| {
+            if output == Path::new(STDOUT) {
+                output.clone()
+            } else {
+                std::path::absolute(output).unwrap_or_else(|_| output.clone())
             }
+        }),
+        format: args.format,
+        tokenizer: args.tokenizer.clone(),
+        max_tokens: args.max_tokens,
+        over_budget: args.over_budget,
+        gitignore: if args.no_recursive_gitignore {
+            Some(IgnoreMode::Root)
+        } else {
+            args.gitignore
+        },
+        max_depth: args.max_depth,
+        hidden: args.hidden.then_some(true),
+        max_file_size: args.max_file_size,
+        file_limits: args.file_limit.clone(),
+        template: args
+            .template
+            .as_ref()
+            .map(|template| std::path::absolute(template).unwrap_or_else(|_| template.clone())),
+        sections,
+    }
+}
+
+fn default_context(name: &str) -> String {
+    match name {
+        "source" => "The following are the relevant source code files:\n".to_string(),
+        "instruct" => "The following are the instructions for the project:\n".to_string(),
+        _ => format!("The following are the {name} files:\n"),
+    }
+}
+
+/// Lists the selection of every section on stdout. A reader that stops early, such as
+/// `head`, is not an error.
+fn print_selection(
+    profile: &Profile,
+    builder: &PromptBuilder,
+) -> Result<(), llm_codebase_to_prompt::Error> {
+    let listing = builder.list()?;
+    let mut stdout = BufWriter::new(io::stdout().lock());
+    let written = profile
+        .sections
+        .iter()
+        .zip(listing)
+        .try_for_each(|(section, files)| {
+            writeln!(stdout, "[{}]", section.name)?;
+            for (path, selection) in files {
+                let mark = if selection.is_included() { '+' } else { '-' };
+                writeln!(stdout, "{mark} {}  ({selection})", path.display())?;
+            }
+            Ok(())
+        });
+    match written.and_then(|()| stdout.flush()) {
+        Err(e) if e.kind() == io::ErrorKind::BrokenPipe => Ok(()),
+        written => Ok(written?),
+    }
+}
+
+fn create_prompt(args: &Cli, profile: &Profile, builder: &PromptBuilder) -> Result<(), String> {
+    let prompt_file_path = output_path(profile, builder.root());
+    let limit = args
+        .chunk_tokens
+        .map(ChunkLimit::Tokens)
+        .or_else(|| args.chunk_bytes.map(ChunkLimit::Bytes));
+    let report = if let Some(limit) = limit {
+        let (parts, report) = builder.split(limit).map_err(|e| e.to_string())?;
+        for (index, part) in parts.iter().enumerate() {
+            let path = part_path(&prompt_file_path, index + 1);
+            fs::write(&path, part).map_err(|e| format!("{}: {e}", path.display()))?;
         }
+        status!(
+            profile,
+            "Split the prompt into {} parts next to {}",
+            parts.len(),
+            prompt_file_path.display()
+        );
+        report
+    } else if is_stdout(profile) {
+        let mut stdout = BufWriter::new(io::stdout().lock());
+        let report = builder.write(&mut stdout).map_err(|e| e.to_string())?;
+        stdout.flush().map_err(|e| e.to_string())?;
+        report
     } else {
-        if let Err(e) = create_prompt(&args, &original_dir) {
-            println!("Error: {}", e);
-            #[cfg(feature = "logging")]
-            error!("Error creating prompt: {}", e);
+        // Written next to the prompt and moved over it, so a failure leaves the previous
+        // prompt in place.
+        let staging = staging_path(&prompt_file_path);
+        let report = write_file(builder, &staging).inspect_err(|_| {
+            let _ = fs::remove_file(&staging);
+        })?;
+        fs::rename(&staging, &prompt_file_path)
+            .map_err(|e| format!("{}: {e}", prompt_file_path.display()))?;
+        status!(profile, "Wrote {}", prompt_file_path.display());
+        report
+    };
+    if let Some(manifest) = &report.manifest {
+        manifest.save(&manifest_path(profile, builder.root()))?;
+    }
+    for section in &report.unmatched {
+        eprintln!("Warning: no file matched the globs of section [{section}]");
+    }
+
+    if let Some(query) = &args.query {
+        status!(profile, "Ranking for `{query}`:");
+        for file in &report.ranking {
+            let marker = if file.included { '+' } else { '-' };
+            status!(
+                profile,
+                "{marker} {:>8.2}  {}",
+                file.score,
+                file.path.display()
+            );
+        }
+    }
+    if args.token_report {
+        for file in &report.included {
+            status!(profile, "{:>10}  {}", file.tokens, file.path.display());
         }
     }
+    if !report.skipped.is_empty() {
+        status!(profile, "Skipped {} files:", report.skipped.len());
+        for file in &report.skipped {
+            status!(profile, "  {}  ({})", file.path.display(), file.reason);
+        }
+    }
+    if !report.minified.is_empty() {
+        let saved: usize = report.minified.iter().map(|(_, saved)| saved).sum();
+        status!(profile, "Minify saved {saved} tokens:");
+        for (path, saved) in &report.minified {
+            status!(profile, "{saved:>10}  {}", path.display());
+        }
+    }
+    if !report.secrets.is_empty() {
+        status!(profile, "Redacted {} secrets:", report.secrets.len());
+        for finding in &report.secrets {
+            status!(profile, "  {finding}");
+        }
+    }
+    if !report.capped.is_empty() {
+        status!(profile, "Cut {} files to their limit:", report.capped.len());
+        for file in &report.capped {
+            status!(profile, "  {file}");
+        }
+    }
+    for change in &report.budget_changes {
+        status!(profile, "Budget: {change}");
+    }
+    status!(profile, "Total tokens: {}", report.total);
+    Ok(())
+}
+
+/// Writes the prompt to the file at `path`.
+fn write_file(builder: &PromptBuilder, path: &Path) -> Result<Report, String> {
+    let file = fs::File::create(path).map_err(|e| format!("{}: {e}", path.display()))?;
+    let mut writer = BufWriter::new(file);
+    let report = builder.write(&mut writer).map_err(|e| e.to_string())?;
+    writer
+        .flush()
+        .map_err(|e| format!("{}: {e}", path.display()))?;
+    Ok(report)
+}
+
+/// Where the prompt is written before it replaces the previous one: hidden next to it.
+fn staging_path(output: &Path) -> PathBuf {
+    let name = output.file_name().unwrap_or_default().to_string_lossy();
+    output.with_file_name(format!(".{name}.partial"))
+}
+
+/// Where the prompt goes. A configured path is relative to the working directory.
+fn output_path(profile: &Profile, root: &Path) -> PathBuf {
+    profile
+        .output
+        .as_ref()
+        .map_or_else(|| PathBuf::from("prompt.txt"), |output| root.join(output))
+}
+
+/// Where the manifest of the last prompt is kept: hidden next to the prompt, or in the
+/// working directory when the prompt goes to stdout.
+fn manifest_path(profile: &Profile, root: &Path) -> PathBuf {
+    if is_stdout(profile) {
+        return root.join(".prompt.manifest.json");
+    }
+    let output = output_path(profile, root);
+    let name = output.file_name().unwrap_or_default().to_string_lossy();
+    output.with_file_name(format!(".{name}.manifest.json"))
+}
+
+/// The path with its directory resolved, so it compares equal to watcher events.
+fn absolute(path: &Path) -> PathBuf {
+    let parent = path
+        .parent()
+        .filter(|parent| !parent.as_os_str().is_empty())
+        .unwrap_or_else(|| Path::new("."));
+    let parent = parent
+        .canonicalize()
+        .unwrap_or_else(|_| parent.to_path_buf());
+    parent.join(path.file_name().unwrap_or_default())
+}
+
+/// Whether `path` is the prompt or one of its numbered parts.
+fn is_output(path: &Path, output: &Path) -> bool {
+    if path == output {
+        return true;
+    }
+    if path.parent() != output.parent() {
+        return false;
+    }
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let stem = output.file_stem().unwrap_or_default().to_string_lossy();
+    let extension = output
+        .extension()
+        .map(|extension| format!(".{}", extension.to_string_lossy()))
+        .unwrap_or_default();
+    name.strip_prefix(&format!("{stem}."))
+        .and_then(|rest| rest.strip_suffix(&extension))
+        .is_some_and(|part| part.len() == 3 && part.bytes().all(|byte| byte.is_ascii_digit()))
+}
 
-    println!("Made prompt.txt file");
-}
-
-fn create_prompt(args: &Cli, original_dir: &PathBuf) -> Result<(), String> {
-    let prompt_file_path = original_dir.join("prompt.txt");
-    let mut output_file = File::create(&prompt_file_path).map_err(|e| e.to_string())?;
-    process_files(
-        &args.source_files,
-        args.source_context.as_deref(),
-        "The following are the relevant source code files:\n",
-        &mut output_file,
-        args.no_recursive_gitignore,
-    )?;
-    process_files(
-        &args.instruct_files,
-        args.instruct_context.as_deref(),
-        "The following are the instructions for the project:\n",
-        &mut output_file,
-        args.no_recursive_gitignore,
-    )
+/// `prompt.txt` becomes `prompt.001.txt`, `prompt.002.txt`, ...
+fn part_path(output: &Path, part: usize) -> PathBuf {
+    let stem = output.file_stem().unwrap_or_default().to_string_lossy();
+    let name = output.extension().map_or_else(
+        || format!("{stem}.{part:03}"),
+        |extension| format!("{stem}.{part:03}.{}", extension.to_string_lossy()),
+    );
+    output.with_file_name(name)
 }
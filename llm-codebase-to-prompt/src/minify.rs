@@ -0,0 +1,714 @@
+use std::ops::Range;
+use std::path::Path;
+
+use crate::language;
+
+/// A stage of the minifier, selected with `--minify`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum MinifyStage {
+    /// Every stage below.
+    All,
+    /// Drop a license or copyright banner at the top of the file.
+    License,
+    /// Remove line comments.
+    Comments,
+    /// Collapse runs of blank lines into one.
+    BlankLines,
+    /// Re-indent with one tab per level, except YAML, Markdown and files of no known
+    /// syntax, and trim trailing whitespace.
+    Indent,
+}
+
+/// Which minifier stages run over each file.
+#[derive(Clone, Copy, Debug, Default)]
+#[allow(clippy::struct_excessive_bools)]
+pub struct MinifyOptions {
+    pub strip_license: bool,
+    pub remove_comments: bool,
+    /// Keep `///`, `//!` and similar documentation comments when removing comments.
+    pub keep_doc_comments: bool,
+    pub collapse_blank_lines: bool,
+    pub normalize_indent: bool,
+}
+
+impl MinifyOptions {
+    /// Enables the given stages.
+    #[must_use]
+    pub fn from_stages(stages: &[MinifyStage], keep_doc_comments: bool) -> Self {
+        let has = |stage| stages.contains(&MinifyStage::All) || stages.contains(&stage);
+        Self {
+            strip_license: has(MinifyStage::License),
+            remove_comments: has(MinifyStage::Comments),
+            keep_doc_comments,
+            collapse_blank_lines: has(MinifyStage::BlankLines),
+            normalize_indent: has(MinifyStage::Indent),
+        }
+    }
+}
+
+/// Words that mark a leading comment block as a license banner.
+const LICENSE_WORDS: [&str; 6] = [
+    "license",
+    "licence",
+    "copyright",
+    "spdx-license-identifier",
+    "permission is hereby granted",
+    "all rights reserved",
+];
+
+/// The comment and string syntax of a family of languages.
+#[allow(clippy::struct_excessive_bools)]
+struct Syntax {
+    line_comments: &'static [&'static str],
+    /// Line comment prefixes that mark documentation.
+    doc_line_comments: &'static [&'static str],
+    block_comment: Option<(&'static str, &'static str)>,
+    /// Block comment openers that mark documentation.
+    doc_block_comments: &'static [&'static str],
+    /// Quote characters that delimit string literals.
+    quotes: &'static [char],
+    /// Whether `'''` and `"""` delimit multi-line strings.
+    triple_quotes: bool,
+    /// Whether line comments must start a line or follow whitespace, as `#` does in shells.
+    comment_after_space: bool,
+    /// Rust-specific literals: raw strings and lifetimes.
+    rust: bool,
+    /// JavaScript regex literals such as `/https?:\/\//`.
+    regex_literals: bool,
+    /// Lua long brackets: `[[ ... ]]` strings and `--[[ ... ]]` comments, with any
+    /// number of `=` between the brackets.
+    long_brackets: bool,
+}
+
+const C_LIKE: Syntax = Syntax {
+    line_comments: &["//"],
+    doc_line_comments: &["///", "//!"],
+    block_comment: Some(("/*", "*/")),
+    doc_block_comments: &["/**", "/*!"],
+    quotes: &['"', '\'', '`'],
+    triple_quotes: false,
+    comment_after_space: false,
+    rust: false,
+    regex_literals: false,
+    long_brackets: false,
+};
+
+const RUST: Syntax = Syntax {
+    quotes: &['"'],
+    rust: true,
+    ..C_LIKE
+};
+
+const JAVASCRIPT: Syntax = Syntax {
+    regex_literals: true,
+    ..C_LIKE
+};
+
+const CSS: Syntax = Syntax {
+    line_comments: &[],
+    doc_line_comments: &[],
+    ..C_LIKE
+};
+
+const HASH: Syntax = Syntax {
+    line_comments: &["#"],
+    doc_line_comments: &[],
+    block_comment: None,
+    doc_block_comments: &[],
+    quotes: &['"', '\''],
+    triple_quotes: false,
+    comment_after_space: true,
+    rust: false,
+    regex_literals: false,
+    long_brackets: false,
+};
+
+const PYTHON: Syntax = Syntax {
+    triple_quotes: true,
+    ..HASH
+};
+
+const DASH: Syntax = Syntax {
+    line_comments: &["--"],
+    doc_line_comments: &["---", "-- |"],
+    block_comment: None,
+    doc_block_comments: &[],
+    quotes: &['"', '\''],
+    triple_quotes: false,
+    comment_after_space: false,
+    rust: false,
+    regex_literals: false,
+    long_brackets: false,
+};
+
+const LUA: Syntax = Syntax {
+    long_brackets: true,
+    ..DASH
+};
+
+const MARKUP: Syntax = Syntax {
+    line_comments: &[],
+    doc_line_comments: &[],
+    block_comment: Some(("<!--", "-->")),
+    doc_block_comments: &[],
+    quotes: &[],
+    triple_quotes: false,
+    comment_after_space: false,
+    rust: false,
+    regex_literals: false,
+    long_brackets: false,
+};
+
+fn syntax(path: &Path) -> Option<Syntax> {
+    let syntax = match language::detect(path)? {
+        "rust" => RUST,
+        "c" | "cpp" | "csharp" | "java" | "kotlin" | "scala" | "go" | "swift" | "dart"
+        | "objectivec" | "php" | "zig" | "protobuf" | "scss" | "less" => C_LIKE,
+        "javascript" | "jsx" | "typescript" | "tsx" => JAVASCRIPT,
+        "css" => CSS,
+        "python" => PYTHON,
+        "ruby" | "perl" | "bash" | "zsh" | "fish" | "r" | "julia" | "elixir" | "nix"
+        | "powershell" | "yaml" | "toml" | "dockerfile" | "makefile" | "cmake" | "graphql"
+        | "gitignore" | "dotenv" => HASH,
+        "sql" | "haskell" => DASH,
+        "lua" => LUA,
+        "html" | "xml" | "vue" | "svelte" | "markdown" => MARKUP,
+        _ => return None,
+    };
+    Some(syntax)
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum SpanKind {
+    LineComment { doc: bool },
+    BlockComment { doc: bool },
+    String,
+}
+
+struct Span {
+    range: Range<usize>,
+    kind: SpanKind,
+}
+
+/// Splits `source` into comment and string spans; everything else is code.
+fn lex(source: &str, syntax: &Syntax) -> Vec<Span> {
+    let bytes = source.as_bytes();
+    let mut spans = Vec::new();
+    let mut index = 0;
+    while index < bytes.len() {
+        let rest = &source[index..];
+        let after_space = index == 0 || bytes[index - 1].is_ascii_whitespace();
+
+        if let Some(end) = rest
+            .strip_prefix("--")
+            .filter(|_| syntax.long_brackets)
+            .and_then(|_| long_bracket_end(source, index + 2))
+        {
+            spans.push(Span {
+                range: index..end,
+                kind: SpanKind::BlockComment { doc: false },
+            });
+            index = end;
+        } else if let Some(prefix) = syntax.line_comments.iter().find(|prefix| {
+            rest.starts_with(**prefix) && (after_space || !syntax.comment_after_space)
+        }) {
+            let end = rest
+                .find('\n')
+                .map_or(source.len(), |offset| index + offset);
+            let doc = syntax
+                .doc_line_comments
+                .iter()
+                .any(|doc| rest.starts_with(doc))
+                && !rest[prefix.len()..].starts_with(*prefix);
+            spans.push(Span {
+                range: index..end,
+                kind: SpanKind::LineComment { doc },
+            });
+            index = end;
+        } else if let Some((open, close)) = syntax
+            .block_comment
+            .filter(|(open, _)| rest.starts_with(open))
+        {
+            let end = rest[open.len()..]
+                .find(close)
+                .map_or(source.len(), |offset| {
+                    index + open.len() + offset + close.len()
+                });
+            let doc = syntax
+                .doc_block_comments
+                .iter()
+                .any(|doc| rest.starts_with(doc))
+                && !rest.starts_with("/**/");
+            spans.push(Span {
+                range: index..end,
+                kind: SpanKind::BlockComment { doc },
+            });
+            index = end;
+        } else if let Some(end) = string_end(source, index, syntax) {
+            spans.push(Span {
+                range: index..end,
+                kind: SpanKind::String,
+            });
+            index = end;
+        } else {
+            index += rest.chars().next().map_or(1, char::len_utf8);
+        }
+    }
+    spans
+}
+
+/// If a string literal starts at `start`, returns the offset just past its end.
+fn string_end(source: &str, start: usize, syntax: &Syntax) -> Option<usize> {
+    let rest = &source[start..];
+    let bytes = rest.as_bytes();
+
+    if syntax.rust {
+        let previous_is_ident = start > 0 && is_ident_byte(source.as_bytes()[start - 1]);
+        // Raw strings: r"..", r#".."#, br#".."#.
+        let raw = rest
+            .strip_prefix("br")
+            .or_else(|| rest.strip_prefix('r'))
+            .filter(|_| !previous_is_ident);
+        if let Some(raw) = raw {
+            let hashes = raw.len() - raw.trim_start_matches('#').len();
+            if raw[hashes..].starts_with('"') {
+                let close = format!("\"{}", "#".repeat(hashes));
+                let body = rest.len() - raw.len() + hashes + 1;
+                return Some(
+                    rest[body..]
+                        .find(&close)
+                        .map_or(source.len(), |offset| start + body + offset + close.len()),
+                );
+            }
+        }
+        // Character literals, but not lifetimes such as `'a`.
+        if bytes[0] == b'\'' {
+            let is_char = bytes.get(1) == Some(&b'\\')
+                || rest[1..]
+                    .chars()
+                    .next()
+                    .is_some_and(|c| rest[1 + c.len_utf8()..].starts_with('\''));
+            return is_char.then(|| quoted_end(source, start, '\''));
+        }
+    }
+
+    if syntax.long_brackets {
+        if let Some(end) = long_bracket_end(source, start) {
+            return Some(end);
+        }
+    }
+
+    if syntax.regex_literals && bytes[0] == b'/' && starts_operand(source, start) {
+        if let Some(end) = regex_end(source, start) {
+            return Some(end);
+        }
+    }
+
+    if syntax.triple_quotes {
+        for quote in ["\"\"\"", "'''"] {
+            if rest.starts_with(quote) {
+                return Some(
+                    rest[3..]
+                        .find(quote)
+                        .map_or(source.len(), |offset| start + 3 + offset + 3),
+                );
+            }
+        }
+    }
+
+    let quote = rest.chars().next()?;
+    syntax
+        .quotes
+        .contains(&quote)
+        .then(|| quoted_end(source, start, quote))
+}
+
+/// Finds the end of a string opened by `quote` at `start`, honoring backslash escapes.
+/// Strings other than backtick templates end at the line end if left unclosed.
+fn quoted_end(source: &str, start: usize, quote: char) -> usize {
+    let mut escaped = false;
+    for (offset, c) in source[start + 1..].char_indices() {
+        let index = start + 1 + offset;
+        if escaped {
+            escaped = false;
+        } else if c == '\\' {
+            escaped = true;
+        } else if c == quote {
+            return index + 1;
+        } else if c == '\n' && quote != '`' && quote != '"' {
+            return index;
+        }
+    }
+    source.len()
+}
+
+/// The comments of a Rust file that are not documentation, in order.
+pub(crate) fn rust_comments(source: &str) -> Vec<Range<usize>> {
+    lex(source, &RUST)
+        .into_iter()
+        .filter(|span| {
+            matches!(
+                span.kind,
+                SpanKind::LineComment { doc: false } | SpanKind::BlockComment { doc: false }
+            )
+        })
+        .map(|span| span.range)
+        .collect()
+}
+
+/// If a Lua long bracket such as `[[` or `[==[` opens at `start`, returns the offset
+/// just past the matching `]]` or `]==]`.
+fn long_bracket_end(source: &str, start: usize) -> Option<usize> {
+    let rest = source[start..].strip_prefix('[')?;
+    let level = rest.len() - rest.trim_start_matches('=').len();
+    rest[level..].strip_prefix('[')?;
+    let close = format!("]{}]", "=".repeat(level));
+    let body = start + level + 2;
+    Some(
+        source[body..]
+            .find(&close)
+            .map_or(source.len(), |offset| body + offset + close.len()),
+    )
+}
+
+/// Whether a JavaScript expression may start at `start`, where a `/` opens a regex
+/// literal rather than dividing: after an operator, an opening bracket, a keyword
+/// such as `return`, or at the start of the file.
+fn starts_operand(source: &str, start: usize) -> bool {
+    let before = source[..start].trim_end();
+    let Some(last) = before.chars().last() else {
+        return true;
+    };
+    if last.is_alphanumeric() || last == '_' || last == '$' {
+        let word_start = before
+            .trim_end_matches(|c: char| c.is_alphanumeric() || c == '_' || c == '$')
+            .len();
+        return matches!(
+            &before[word_start..],
+            "return"
+                | "typeof"
+                | "case"
+                | "do"
+                | "else"
+                | "in"
+                | "of"
+                | "new"
+                | "delete"
+                | "void"
+                | "throw"
+                | "instanceof"
+                | "yield"
+                | "await"
+        );
+    }
+    !matches!(last, ')' | ']' | '}' | '"' | '\'' | '`')
+}
+
+/// If a regex literal opened at `start` closes on the same line, returns the offset
+/// just past it and its flags. A `/` inside a character class does not close it.
+fn regex_end(source: &str, start: usize) -> Option<usize> {
+    let mut escaped = false;
+    let mut in_class = false;
+    for (offset, c) in source[start + 1..].char_indices() {
+        match c {
+            '\n' => return None,
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            '[' => in_class = true,
+            ']' => in_class = false,
+            '/' if !in_class => {
+                let end = start + 1 + offset + 1;
+                return Some(
+                    end + source[end..].len()
+                        - source[end..]
+                            .trim_start_matches(|c: char| c.is_ascii_alphabetic())
+                            .len(),
+                );
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+fn is_shebang(source: &str, span: &Span) -> bool {
+    span.range.start == 0 && source.starts_with("#!")
+}
+
+const fn is_ident_byte(byte: u8) -> bool {
+    byte.is_ascii_alphanumeric() || byte == b'_'
+}
+
+/// Minifies a file, returning each kept line with the zero-based number of the line
+/// it came from in `source`. Lines inside multi-line string literals are never changed.
+#[must_use]
+pub fn minify_lines(path: &Path, source: &str, options: &MinifyOptions) -> Vec<(usize, String)> {
+    let syntax = syntax(path);
+    let spans = syntax
+        .as_ref()
+        .map(|syntax| lex(source, syntax))
+        .unwrap_or_default();
+
+    let mut removed: Vec<Range<usize>> = Vec::new();
+    if options.strip_license {
+        removed.extend(license_banner(source, &spans));
+    }
+    if options.remove_comments {
+        removed.extend(spans.iter().filter_map(|span| match span.kind {
+            SpanKind::LineComment { doc }
+                if !(is_shebang(source, span) || doc && options.keep_doc_comments) =>
+            {
+                Some(span.range.clone())
+            }
+            _ => None,
+        }));
+    }
+    removed.sort_by_key(|range| range.start);
+    let strings: Vec<&Range<usize>> = spans
+        .iter()
+        .filter(|span| span.kind == SpanKind::String)
+        .map(|span| &span.range)
+        .collect();
+    let inside_string = |offset: usize| {
+        strings
+            .iter()
+            .any(|range| range.start < offset && offset < range.end)
+    };
+
+    let unit = if options.normalize_indent && tabs_keep_meaning(path) {
+        indent_unit(source)
+    } else {
+        None
+    };
+    let mut lines: Vec<(usize, String)> = Vec::new();
+    let mut offset = 0;
+    for (number, line) in source.split_inclusive('\n').enumerate() {
+        let start = offset;
+        offset += line.len();
+        let line = line.strip_suffix('\n').unwrap_or(line);
+        let line = line.strip_suffix('\r').unwrap_or(line);
+        let end = start + line.len();
+
+        if inside_string(start) {
+            lines.push((number, line.to_string()));
+            continue;
+        }
+        let mut text = cut(line, start, &removed);
+        if text.trim().is_empty() && !line.trim().is_empty() {
+            continue;
+        }
+        if options.normalize_indent && !inside_string(end) {
+            text.truncate(text.trim_end().len());
+        }
+        if let Some(unit) = unit {
+            text = reindent(&text, unit);
+        }
+        let blank = text.trim().is_empty();
+        if blank
+            && options.collapse_blank_lines
+            && lines
+                .last()
+                .is_none_or(|(_, previous)| previous.trim().is_empty())
+        {
+            continue;
+        }
+        lines.push((number, text));
+    }
+    if options.collapse_blank_lines {
+        while lines.last().is_some_and(|(_, text)| text.trim().is_empty()) {
+            lines.pop();
+        }
+    }
+    lines
+}
+
+/// Minifies a file, see [`minify_lines`].
+#[must_use]
+pub fn minify(path: &Path, source: &str, options: &MinifyOptions) -> String {
+    let mut minified: String = minify_lines(path, source, options)
+        .into_iter()
+        .map(|(_, line)| line)
+        .collect::<Vec<_>>()
+        .join("\n");
+    if source.ends_with('\n') && !minified.is_empty() {
+        minified.push('\n');
+    }
+    minified
+}
+
+/// Removes the parts of `line` (starting at byte `start` of the file) covered by `removed`.
+fn cut(line: &str, start: usize, removed: &[Range<usize>]) -> String {
+    let end = start + line.len();
+    let mut text = String::with_capacity(line.len());
+    let mut position = start;
+    for range in removed
+        .iter()
+        .filter(|range| range.start < end && range.end > start)
+    {
+        let from = range.start.max(start);
+        if from > position {
+            text.push_str(&line[position - start..from - start]);
+        }
+        position = position.max(range.end.min(end));
+    }
+    if position < end {
+        text.push_str(&line[position - start..]);
+    }
+    if text.len() < line.len() {
+        text.truncate(text.trim_end().len());
+    }
+    text
+}
+
+/// The comments at the very top of the file, if they read like a license.
+fn license_banner(source: &str, spans: &[Span]) -> Vec<Range<usize>> {
+    let mut position = if source.starts_with("#!") {
+        source.find('\n').unwrap_or(source.len())
+    } else {
+        0
+    };
+    let mut banner = Vec::new();
+    for span in spans {
+        if span.range.start < position {
+            continue;
+        }
+        let is_comment = matches!(
+            span.kind,
+            SpanKind::LineComment { doc: false } | SpanKind::BlockComment { doc: false }
+        );
+        if !is_comment || !source[position..span.range.start].trim().is_empty() {
+            break;
+        }
+        banner.push(span.range.clone());
+        position = span.range.end;
+    }
+
+    let text = banner
+        .iter()
+        .map(|range| source[range.clone()].to_lowercase())
+        .collect::<String>();
+    if LICENSE_WORDS.iter().any(|word| text.contains(word)) {
+        banner
+    } else {
+        Vec::new()
+    }
+}
+
+/// Whether re-indenting the file with tabs leaves its meaning alone: not for YAML,
+/// where tabs are illegal, Markdown, where indentation nests blocks, or files of no
+/// known syntax.
+fn tabs_keep_meaning(path: &Path) -> bool {
+    syntax(path).is_some() && !matches!(language::detect(path), Some("yaml" | "markdown"))
+}
+
+/// The number of spaces per indentation level, when the file is indented with spaces.
+fn indent_unit(source: &str) -> Option<usize> {
+    let mut unit = 0;
+    for line in source.lines().filter(|line| !line.trim().is_empty()) {
+        if line.starts_with('\t') {
+            return None;
+        }
+        let spaces = line.len() - line.trim_start_matches(' ').len();
+        if spaces > 0 {
+            unit = gcd(unit, spaces);
+        }
+    }
+    (unit >= 2).then_some(unit)
+}
+
+const fn gcd(a: usize, b: usize) -> usize {
+    if b == 0 {
+        a
+    } else {
+        gcd(b, a % b)
+    }
+}
+
+fn reindent(line: &str, unit: usize) -> String {
+    let spaces = line.len() - line.trim_start_matches(' ').len();
+    format!(
+        "{}{}{}",
+        "\t".repeat(spaces / unit),
+        " ".repeat(spaces % unit),
+        &line[spaces..]
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn all(keep_doc_comments: bool) -> MinifyOptions {
+        MinifyOptions::from_stages(&[MinifyStage::All], keep_doc_comments)
+    }
+
+    fn comments() -> MinifyOptions {
+        MinifyOptions::from_stages(&[MinifyStage::Comments], false)
+    }
+
+    #[test]
+    fn javascript_regex_literals_are_not_comments() {
+        let source = "const url = /https?:\\/\\//g; // Scheme.\nconst slash = /[/]/;\nconst half = total / 2 / count; // Ratio.\n";
+        assert_eq!(
+            minify(Path::new("a.js"), source, &comments()),
+            "const url = /https?:\\/\\//g;\nconst slash = /[/]/;\nconst half = total / 2 / count;\n"
+        );
+    }
+
+    #[test]
+    fn regex_after_a_keyword_is_a_literal() {
+        let source = "function f(s) {\n  return /\\/\\/.*/.test(s);\n}\n";
+        assert_eq!(minify(Path::new("a.ts"), source, &comments()), source);
+    }
+
+    #[test]
+    fn lua_long_brackets_are_kept_whole() {
+        let source = "--[[ Multi-line\n-- still a comment\n]]\nlocal s = [==[ -- not a comment ]==] -- Trailing.\nprint(s)\n";
+        assert_eq!(
+            minify(Path::new("a.lua"), source, &comments()),
+            "--[[ Multi-line\n-- still a comment\n]]\nlocal s = [==[ -- not a comment ]==]\nprint(s)\n"
+        );
+    }
+
+    #[test]
+    fn comment_markers_inside_strings_are_kept() {
+        let source =
+            "let url = \"https://example.com\"; // Home.\nlet raw = r#\"// not a comment\"#;\n";
+        assert_eq!(
+            minify(Path::new("a.rs"), source, &comments()),
+            "let url = \"https://example.com\";\nlet raw = r#\"// not a comment\"#;\n"
+        );
+    }
+
+    #[test]
+    fn doc_comments_can_be_kept() {
+        let source = "/// Adds.\n// Helper.\nfn add() {}\n";
+        assert_eq!(
+            minify(Path::new("a.rs"), source, &all(true)),
+            "/// Adds.\nfn add() {}\n"
+        );
+        assert_eq!(
+            minify(Path::new("a.rs"), source, &all(false)),
+            "fn add() {}\n"
+        );
+    }
+
+    #[test]
+    fn license_banners_and_blank_runs_are_removed() {
+        let source = "// Copyright 2024 Example Corp.\n// Licensed under MIT.\n\nfn a() {\n    let x = 1;\n\n\n    x\n}\n";
+        assert_eq!(
+            minify(Path::new("a.rs"), source, &all(false)),
+            "fn a() {\n\tlet x = 1;\n\n\tx\n}\n"
+        );
+    }
+
+    #[test]
+    fn yaml_and_unknown_files_keep_their_indentation() {
+        let source = "jobs:\n  test:\n    runs-on: ubuntu-latest\n";
+        assert_eq!(minify(Path::new("ci.yml"), source, &all(false)), source);
+        assert_eq!(
+            minify(Path::new("notes.unknown"), "a\n    b\n", &all(false)),
+            "a\n    b\n"
+        );
+    }
+}
@@ -0,0 +1,325 @@
+use crate::minify;
+use std::ops::Range;
+use syn::spanned::Spanned;
+use syn::visit::{self, Visit};
+use syn::{
+    Block, Expr, ImplItemConst, ImplItemFn, ItemConst, ItemFn, ItemMacro, ItemStatic,
+    MacroDelimiter, TraitItemConst, TraitItemFn,
+};
+
+/// What every function and `macro_rules!` body is replaced with.
+const ELIDED_BODY: &str = "{ ... }";
+
+/// What every `const` and `static` initializer is replaced with.
+const ELIDED_VALUE: &str = "...";
+
+/// A part of the source left out of the outline, and what takes its place.
+struct Elision {
+    range: Range<usize>,
+    replacement: &'static str,
+}
+
+/// Reduces a Rust source file to its API shape.
+///
+/// Every function and `macro_rules!` body becomes `{ ... }`, every `const` and
+/// `static` initializer becomes `...`, and comments other than doc comments are
+/// dropped, while signatures, type definitions, trait declarations, `impl` headers and
+/// doc comments are kept exactly as written.
+///
+/// # Errors
+/// Returns the parse error if `source` is not valid Rust.
+pub fn outline_rust(source: &str) -> Result<String, String> {
+    let mut outline = source.to_string();
+    for elision in elisions(source)?.iter().rev() {
+        outline.replace_range(elision.range.clone(), elision.replacement);
+    }
+    Ok(outline)
+}
+
+/// Outlines a file as [`outline_rust`] does, returning each line of the outline with
+/// the zero-based number of the line of `source` it starts on.
+///
+/// # Errors
+/// Returns the parse error if `source` is not valid Rust.
+pub fn outline_rust_lines(source: &str) -> Result<Vec<(usize, String)>, String> {
+    let mut lines = vec![(0, String::new())];
+    let mut number = 0;
+    let mut position = 0;
+    for elision in elisions(source)? {
+        let range = elision.range;
+        push_text(&mut lines, &mut number, &source[position..range.start]);
+        push_text(&mut lines, &mut number, elision.replacement);
+        number += source[range.clone()].matches('\n').count();
+        // A line dropped whole leaves the next one to start where it was.
+        if let Some((first, _)) = lines.last_mut().filter(|(_, line)| line.is_empty()) {
+            *first = number;
+        }
+        position = range.end;
+    }
+    push_text(&mut lines, &mut number, &source[position..]);
+    if source.ends_with('\n') {
+        lines.pop();
+    }
+    for (_, line) in &mut lines {
+        if line.ends_with('\r') {
+            line.pop();
+        }
+    }
+    Ok(lines)
+}
+
+/// Appends `text` to the last line, starting a new one, numbered `number`, at each newline.
+fn push_text(lines: &mut Vec<(usize, String)>, number: &mut usize, text: &str) {
+    for (index, piece) in text.split('\n').enumerate() {
+        if index > 0 {
+            *number += 1;
+            lines.push((*number, String::new()));
+        }
+        if let Some((_, line)) = lines.last_mut() {
+            line.push_str(piece);
+        }
+    }
+}
+
+/// Everything left out of the outline of `source`, outermost only, in order.
+fn elisions(source: &str) -> Result<Vec<Elision>, String> {
+    let parsed = syn::parse_file(source).map(|file| {
+        let mut bodies = Bodies::default();
+        bodies.visit_file(&file);
+        bodies.elisions
+    });
+    // Span locations keep a copy of every parsed source for the life of the thread.
+    proc_macro2::extra::invalidate_current_thread_spans();
+    let mut elisions = parsed.map_err(|e| e.to_string())?;
+    elisions.extend(
+        minify::rust_comments(source)
+            .into_iter()
+            .map(|range| Elision {
+                range: comment_lines(source, range),
+                replacement: "",
+            }),
+    );
+    elisions.sort_by_key(|elision| elision.range.start);
+    let mut end = 0;
+    elisions.retain(|elision| {
+        let outermost = elision.range.start >= end;
+        if outermost {
+            end = elision.range.end;
+        }
+        outermost
+    });
+    Ok(elisions)
+}
+
+/// Widens a comment to its whole line when nothing else is on it, and otherwise to the
+/// spaces before it.
+fn comment_lines(source: &str, range: Range<usize>) -> Range<usize> {
+    let before = source[..range.start].trim_end_matches([' ', '\t']).len();
+    let after = source[range.end..].trim_start_matches([' ', '\t', '\r']);
+    let line_start = before == 0 || source.as_bytes()[before - 1] == b'\n';
+    if line_start && (after.is_empty() || after.starts_with('\n')) {
+        let end = source.len() - after.len() + usize::from(after.starts_with('\n'));
+        before..end
+    } else {
+        before..range.end
+    }
+}
+
+/// Collects the function and `macro_rules!` bodies and the `const` and `static`
+/// initializers, outermost only.
+#[derive(Default)]
+struct Bodies {
+    elisions: Vec<Elision>,
+}
+
+impl Bodies {
+    fn push(&mut self, block: &Block) {
+        self.elide(block.brace_token.span.join().byte_range(), ELIDED_BODY);
+    }
+
+    fn push_value(&mut self, expr: &Expr) {
+        self.elide(expr.span().byte_range(), ELIDED_VALUE);
+    }
+
+    fn elide(&mut self, range: Range<usize>, replacement: &'static str) {
+        if !range.is_empty() {
+            self.elisions.push(Elision { range, replacement });
+        }
+    }
+}
+
+impl<'ast> Visit<'ast> for Bodies {
+    fn visit_item_fn(&mut self, item: &'ast ItemFn) {
+        self.push(&item.block);
+    }
+
+    fn visit_impl_item_fn(&mut self, item: &'ast ImplItemFn) {
+        self.push(&item.block);
+    }
+
+    fn visit_trait_item_fn(&mut self, item: &'ast TraitItemFn) {
+        match &item.default {
+            Some(block) => self.push(block),
+            None => visit::visit_trait_item_fn(self, item),
+        }
+    }
+
+    fn visit_item_const(&mut self, item: &'ast ItemConst) {
+        self.push_value(&item.expr);
+    }
+
+    fn visit_item_static(&mut self, item: &'ast ItemStatic) {
+        self.push_value(&item.expr);
+    }
+
+    fn visit_impl_item_const(&mut self, item: &'ast ImplItemConst) {
+        self.push_value(&item.expr);
+    }
+
+    fn visit_trait_item_const(&mut self, item: &'ast TraitItemConst) {
+        if let Some((_, expr)) = &item.default {
+            self.push_value(expr);
+        }
+    }
+
+    fn visit_item_macro(&mut self, item: &'ast ItemMacro) {
+        if item.mac.path.is_ident("macro_rules") {
+            let span = match &item.mac.delimiter {
+                MacroDelimiter::Paren(paren) => paren.span.join(),
+                MacroDelimiter::Brace(brace) => brace.span.join(),
+                MacroDelimiter::Bracket(bracket) => bracket.span.join(),
+            };
+            self.elide(span.byte_range(), ELIDED_BODY);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOURCE: &str = "\
+// Copyright 2024 Example Corp. Licensed under MIT.
+
+//! The crate.
+
+/// The answer.
+pub const ANSWER: u32 = 6 * 7; // Computed.
+static NAMES: &[&str] = &[
+    \"a\",
+    \"b\",
+];
+
+macro_rules! double {
+    ($x:expr) => {
+        $x * 2
+    };
+}
+
+/// A thing.
+pub struct Thing {
+    // Internal.
+    value: u32, /* Kept small. */
+}
+
+impl Thing {
+    const LIMIT: u32 = 10;
+
+    /// Makes a thing.
+    pub fn new() -> Self {
+        // Start at zero.
+        Self { value: 0 }
+    }
+}
+
+trait Shape {
+    const SIDES: u32 = 4;
+    fn area(&self) -> f64;
+    fn name(&self) -> &str {
+        \"shape\"
+    }
+}
+";
+
+    #[test]
+    fn keeps_only_signatures_definitions_and_doc_comments() {
+        let expected = "\
+\n//! The crate.
+
+/// The answer.
+pub const ANSWER: u32 = ...;
+static NAMES: &[&str] = ...;
+
+macro_rules! double { ... }
+
+/// A thing.
+pub struct Thing {
+    value: u32,
+}
+
+impl Thing {
+    const LIMIT: u32 = ...;
+
+    /// Makes a thing.
+    pub fn new() -> Self { ... }
+}
+
+trait Shape {
+    const SIDES: u32 = ...;
+    fn area(&self) -> f64;
+    fn name(&self) -> &str { ... }
+}
+";
+        assert_eq!(outline_rust(SOURCE).unwrap(), expected);
+    }
+
+    #[test]
+    fn outline_lines_keep_the_number_of_the_line_they_came_from() {
+        let lines = outline_rust_lines(SOURCE).unwrap();
+        let numbered: Vec<(usize, &str)> = lines
+            .iter()
+            .map(|(number, line)| (*number, line.as_str()))
+            .collect();
+        let source: Vec<&str> = SOURCE.lines().collect();
+        for (number, line) in &numbered {
+            let original = source[*number];
+            let kept = line
+                .split(" ...")
+                .next()
+                .unwrap()
+                .trim_end_matches(" =")
+                .trim_end_matches(" {");
+            assert!(
+                original.starts_with(kept),
+                "line {number}: {line:?} is not from {original:?}"
+            );
+        }
+        assert_eq!(
+            numbered[..4],
+            [
+                (1, ""),
+                (2, "//! The crate."),
+                (3, ""),
+                (4, "/// The answer.")
+            ]
+        );
+        assert_eq!(
+            numbered.len(),
+            outline_rust(SOURCE).unwrap().lines().count()
+        );
+    }
+
+    #[test]
+    fn comment_markers_inside_strings_are_kept() {
+        let source = "fn url() -> &'static str { \"x\" }\nconst URL: &str = \"https://example.com\";\nstruct S; // Trailing.\n";
+        assert_eq!(
+            outline_rust(source).unwrap(),
+            "fn url() -> &'static str { ... }\nconst URL: &str = ...;\nstruct S;\n"
+        );
+    }
+
+    #[test]
+    fn invalid_rust_is_an_error() {
+        assert!(outline_rust("fn broken( {").is_err());
+    }
+}
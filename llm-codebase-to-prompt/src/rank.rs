@@ -0,0 +1,191 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// BM25 term frequency saturation.
+const K1: f64 = 1.2;
+/// BM25 document length normalization.
+const B: f64 = 0.75;
+/// How many times a term of the file's path counts compared to one in its contents.
+const PATH_WEIGHT: u32 = 3;
+
+/// Query words that carry no meaning about which file is wanted.
+const STOP_WORDS: &[&str] = &[
+    "a", "an", "and", "are", "as", "at", "be", "bug", "by", "can", "do", "does", "fix", "for",
+    "from", "how", "i", "in", "is", "it", "make", "not", "of", "on", "or", "so", "that", "the",
+    "this", "to", "we", "what", "when", "where", "why", "with",
+];
+
+/// The terms of one candidate file: the words of its path, identifiers and comments.
+pub struct Document {
+    pub path: PathBuf,
+    terms: HashMap<String, u32>,
+    length: u32,
+}
+
+impl Document {
+    #[must_use]
+    pub fn new(path: &Path, text: &str) -> Self {
+        let mut terms = HashMap::new();
+        let mut length = 0;
+        for term in terms_of(text) {
+            *terms.entry(term).or_insert(0) += 1;
+            length += 1;
+        }
+        for term in terms_of(&path.to_string_lossy()) {
+            *terms.entry(term).or_insert(0) += PATH_WEIGHT;
+            length += PATH_WEIGHT;
+        }
+        Self {
+            path: path.to_path_buf(),
+            terms,
+            length,
+        }
+    }
+}
+
+/// Scores every document against `query` with BM25 and returns those matching at
+/// least one term, best first; ties keep path order.
+#[must_use]
+pub fn rank(query: &str, documents: &[Document]) -> Vec<(PathBuf, f64)> {
+    let mut query_terms: Vec<String> = terms_of(query)
+        .filter(|term| !STOP_WORDS.contains(&term.as_str()))
+        .collect();
+    query_terms.sort();
+    query_terms.dedup();
+    if documents.is_empty() || query_terms.is_empty() {
+        return Vec::new();
+    }
+
+    let count = f64::from(u32::try_from(documents.len()).unwrap_or(u32::MAX));
+    let average_length = documents
+        .iter()
+        .map(|document| f64::from(document.length))
+        .sum::<f64>()
+        / count;
+    let idf: Vec<f64> = query_terms
+        .iter()
+        .map(|term| {
+            let frequency = documents
+                .iter()
+                .filter(|document| document.terms.contains_key(term))
+                .count();
+            let frequency = f64::from(u32::try_from(frequency).unwrap_or(u32::MAX));
+            ((count - frequency + 0.5) / (frequency + 0.5)).ln_1p()
+        })
+        .collect();
+
+    let mut scores: Vec<(PathBuf, f64)> = documents
+        .iter()
+        .map(|document| {
+            let length = f64::from(document.length) / average_length.max(1.0);
+            let score = query_terms
+                .iter()
+                .zip(&idf)
+                .map(|(term, idf)| {
+                    let frequency = f64::from(document.terms.get(term).copied().unwrap_or(0));
+                    idf * frequency * (K1 + 1.0) / K1.mul_add(B.mul_add(length, 1.0 - B), frequency)
+                })
+                .sum::<f64>();
+            (document.path.clone(), score)
+        })
+        .filter(|(_, score)| *score > 0.0)
+        .collect();
+    scores.sort_by(|(a_path, a), (b_path, b)| b.total_cmp(a).then_with(|| a_path.cmp(b_path)));
+    scores
+}
+
+/// Lowercase words of `text`. Identifiers count both whole and split into their
+/// `snake_case` and `camelCase` parts, so `parseHttpHeader` matches `http`.
+fn terms_of(text: &str) -> impl Iterator<Item = String> + '_ {
+    text.split(|c: char| !c.is_alphanumeric() && c != '_')
+        .filter(|word| !word.is_empty())
+        .flat_map(|word| {
+            let parts = split_identifier(word);
+            let whole = (parts.len() > 1).then(|| word.trim_matches('_').to_lowercase());
+            parts.into_iter().chain(whole)
+        })
+        .filter(|term| term.chars().count() > 1 && !term.chars().all(|c| c.is_ascii_digit()))
+}
+
+/// Splits an identifier at underscores and case changes: `HTTPServer_config` becomes
+/// `http`, `server` and `config`.
+fn split_identifier(word: &str) -> Vec<String> {
+    let mut parts = Vec::new();
+    for piece in word.split('_').filter(|piece| !piece.is_empty()) {
+        let chars: Vec<char> = piece.chars().collect();
+        let mut start = 0;
+        for i in 1..chars.len() {
+            let (previous, current) = (chars[i - 1], chars[i]);
+            let next_is_lower = chars.get(i + 1).is_some_and(|c| c.is_lowercase());
+            let boundary = (previous.is_lowercase() && current.is_uppercase())
+                || (previous.is_uppercase() && current.is_uppercase() && next_is_lower)
+                || (previous.is_alphabetic() != current.is_alphabetic());
+            if boundary {
+                parts.push(chars[start..i].iter().collect::<String>().to_lowercase());
+                start = i;
+            }
+        }
+        parts.push(chars[start..].iter().collect::<String>().to_lowercase());
+    }
+    parts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ranked(query: &str, documents: &[(&str, &str)]) -> Vec<String> {
+        let documents: Vec<Document> = documents
+            .iter()
+            .map(|(path, text)| Document::new(Path::new(path), text))
+            .collect();
+        rank(query, &documents)
+            .into_iter()
+            .map(|(path, _)| path.to_string_lossy().into_owned())
+            .collect()
+    }
+
+    #[test]
+    fn files_matching_more_of_the_query_rank_first() {
+        let documents = [
+            ("src/db.rs", "fn connect() {}\nfn query() {}"),
+            (
+                "src/http.rs",
+                "fn parseHttpHeader() {}\n// reads the request header",
+            ),
+            ("src/header.rs", "fn render_header() {}"),
+            ("README.md", "A tool."),
+        ];
+        assert_eq!(
+            ranked("fix the http header parsing", &documents),
+            ["src/http.rs", "src/header.rs"]
+        );
+    }
+
+    #[test]
+    fn path_terms_outweigh_a_mention_in_the_contents() {
+        let documents = [
+            ("src/cache.rs", "fn get() {}"),
+            ("src/store.rs", "fn get() {} // not a cache"),
+        ];
+        assert_eq!(
+            ranked("cache", &documents),
+            ["src/cache.rs", "src/store.rs"]
+        );
+    }
+
+    #[test]
+    fn stop_words_alone_match_nothing() {
+        assert!(ranked("how to fix this", &[("src/fix.rs", "fix how this")]).is_empty());
+    }
+
+    #[test]
+    fn identifiers_split_into_their_parts() {
+        assert_eq!(
+            split_identifier("HTTPServer_config"),
+            ["http", "server", "config"]
+        );
+        let terms: Vec<String> = terms_of("parseHttpHeader x 42").collect();
+        assert_eq!(terms, ["parse", "http", "header", "parsehttpheader"]);
+    }
+}
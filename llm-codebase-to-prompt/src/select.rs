@@ -0,0 +1,157 @@
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use ignore::Match;
+use std::fmt;
+use std::path::Path;
+
+/// Decides which files belong to a section using gitignore-style include and exclude
+/// globs.
+///
+/// `!pattern` negates a rule, braces expand (`*.{rs,toml}`), a pattern without
+/// a slash matches at any depth and a trailing slash matches a whole directory.
+/// Within each list the last matching rule wins, and a matching exclude always beats
+/// an include.
+pub struct Selector {
+    include: Gitignore,
+    exclude: Gitignore,
+}
+
+/// Why a file was or was not selected, with the rule responsible.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Selection {
+    Included(String),
+    Excluded(String),
+    Unmatched,
+}
+
+impl Selection {
+    #[must_use]
+    pub const fn is_included(&self) -> bool {
+        matches!(self, Self::Included(_))
+    }
+}
+
+impl fmt::Display for Selection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Included(rule) => write!(f, "included by `{rule}`"),
+            Self::Excluded(rule) => write!(f, "excluded by `{rule}`"),
+            Self::Unmatched => write!(f, "not matched by any include"),
+        }
+    }
+}
+
+impl Selector {
+    /// Builds a selector rooted at the working directory.
+    ///
+    /// # Errors
+    /// Returns an error naming the first pattern that is not a valid glob.
+    pub fn new(include: &[String], exclude: &[String]) -> Result<Self, String> {
+        Ok(Self {
+            include: build(include)?,
+            exclude: build(exclude)?,
+        })
+    }
+
+    /// Matches a path relative to the working directory.
+    #[must_use]
+    pub fn select(&self, path: &Path) -> Selection {
+        let path = path.strip_prefix(".").unwrap_or(path);
+        if let Match::Ignore(glob) = self.exclude.matched_path_or_any_parents(path, false) {
+            return Selection::Excluded(glob.original().to_string());
+        }
+        match self.include.matched_path_or_any_parents(path, false) {
+            Match::Ignore(glob) => Selection::Included(glob.original().to_string()),
+            Match::Whitelist(glob) => Selection::Excluded(glob.original().to_string()),
+            Match::None => Selection::Unmatched,
+        }
+    }
+}
+
+fn build(patterns: &[String]) -> Result<Gitignore, String> {
+    let mut builder = GitignoreBuilder::new(".");
+    for pattern in patterns {
+        builder
+            .add_line(None, &anchor(pattern))
+            .map_err(|e| format!("Invalid pattern [{pattern}]: {e}"))?;
+    }
+    builder.build().map_err(|e| e.to_string())
+}
+
+/// Turns a leading `./` into `/`, which anchors the pattern at the working directory
+/// as walked paths are matched without their `./`.
+fn anchor(pattern: &str) -> String {
+    let (negation, rest) = pattern
+        .strip_prefix('!')
+        .map_or(("", pattern), |rest| ("!", rest));
+    rest.strip_prefix("./").map_or_else(
+        || pattern.to_string(),
+        |rest| format!("{negation}/{}", rest.trim_start_matches('/')),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn selector(include: &[&str], exclude: &[&str]) -> Selector {
+        let owned =
+            |patterns: &[&str]| patterns.iter().map(ToString::to_string).collect::<Vec<_>>();
+        Selector::new(&owned(include), &owned(exclude)).unwrap()
+    }
+
+    #[test]
+    fn dot_slash_patterns_are_anchored_at_the_root() {
+        let selector = selector(&["./src/*.rs", "!./src/gen.rs"], &["./src/skip.rs"]);
+        assert_eq!(
+            selector.select(Path::new("./src/main.rs")),
+            Selection::Included("/src/*.rs".to_string())
+        );
+        assert_eq!(
+            selector.select(Path::new("./lib/src/main.rs")),
+            Selection::Unmatched
+        );
+        assert_eq!(
+            selector.select(Path::new("./src/gen.rs")),
+            Selection::Excluded("!/src/gen.rs".to_string())
+        );
+        assert_eq!(
+            selector.select(Path::new("./src/skip.rs")),
+            Selection::Excluded("/src/skip.rs".to_string())
+        );
+    }
+
+    #[test]
+    fn patterns_without_a_slash_match_at_any_depth() {
+        let selector = selector(&["*.rs"], &["target/"]);
+        assert!(selector.select(Path::new("./a/b/c.rs")).is_included());
+        assert_eq!(
+            selector.select(Path::new("./target/debug/build.rs")),
+            Selection::Excluded("target/".to_string())
+        );
+        assert_eq!(
+            selector.select(Path::new("./README.md")),
+            Selection::Unmatched
+        );
+    }
+
+    #[test]
+    fn braces_expand_and_the_last_include_wins() {
+        let selector = selector(
+            &[
+                "src/**/*.{rs,toml}",
+                "!src/generated/**",
+                "src/generated/keep.rs",
+            ],
+            &[],
+        );
+        assert!(selector
+            .select(Path::new("./src/a/Cargo.toml"))
+            .is_included());
+        assert!(!selector
+            .select(Path::new("./src/generated/other.rs"))
+            .is_included());
+        assert!(selector
+            .select(Path::new("./src/generated/keep.rs"))
+            .is_included());
+    }
+}
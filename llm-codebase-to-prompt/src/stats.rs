@@ -0,0 +1,101 @@
+use crate::{language, SourceFile};
+use std::fmt;
+use std::path::PathBuf;
+
+/// How many of the largest files are listed.
+const LARGEST: usize = 5;
+
+/// What a prompt is made of: its files by language, the largest ones, and its size.
+#[derive(Clone, Debug, Default)]
+pub struct Stats {
+    /// Largest first, by tokens.
+    pub languages: Vec<LanguageStats>,
+    /// The files taking the most tokens, largest first.
+    pub largest: Vec<(PathBuf, usize)>,
+    /// Tokens of the whole prompt, contexts and header included.
+    pub total: usize,
+}
+
+/// The files of one language, or of no known language under `other`.
+#[derive(Clone, Debug)]
+pub struct LanguageStats {
+    pub language: &'static str,
+    pub files: usize,
+    pub lines: usize,
+    /// Tokens taken by the files once written, headers included.
+    pub tokens: usize,
+}
+
+impl Stats {
+    /// Counts the files of every section of a prompt of `total` tokens.
+    #[must_use]
+    pub fn new(sections: &[Vec<SourceFile>], total: usize) -> Self {
+        let mut stats = Self::default();
+        for file in sections.iter().flatten() {
+            stats.add(file);
+        }
+        stats.total = total;
+        stats
+    }
+
+    /// Counts one more file, keeping the languages and largest files in order.
+    pub(crate) fn add(&mut self, file: &SourceFile) {
+        let language = language::detect(&file.path).unwrap_or("other");
+        let lines = file.contents.lines().count();
+        match self
+            .languages
+            .iter_mut()
+            .find(|entry| entry.language == language)
+        {
+            Some(entry) => {
+                entry.files += 1;
+                entry.lines += lines;
+                entry.tokens += file.tokens;
+            }
+            None => self.languages.push(LanguageStats {
+                language,
+                files: 1,
+                lines,
+                tokens: file.tokens,
+            }),
+        }
+        self.languages.sort_by(|a, b| {
+            b.tokens
+                .cmp(&a.tokens)
+                .then_with(|| a.language.cmp(b.language))
+        });
+
+        self.largest.push((file.path.clone(), file.tokens));
+        self.largest
+            .sort_by(|(a_path, a), (b_path, b)| b.cmp(a).then_with(|| a_path.cmp(b_path)));
+        self.largest.truncate(LARGEST);
+    }
+}
+
+impl fmt::Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{:<12} {:>6} {:>8} {:>8}",
+            "language", "files", "lines", "tokens"
+        )?;
+        for language in &self.languages {
+            writeln!(
+                f,
+                "{:<12} {:>6} {:>8} {:>8}",
+                language.language, language.files, language.lines, language.tokens
+            )?;
+        }
+        let files: usize = self.languages.iter().map(|language| language.files).sum();
+        let lines: usize = self.languages.iter().map(|language| language.lines).sum();
+        let tokens: usize = self.languages.iter().map(|language| language.tokens).sum();
+        writeln!(f, "{:<12} {files:>6} {lines:>8} {tokens:>8}", "all")?;
+        if !self.largest.is_empty() {
+            writeln!(f, "\nLargest files:")?;
+            for (path, tokens) in &self.largest {
+                writeln!(f, "{tokens:>8}  {}", path.display())?;
+            }
+        }
+        writeln!(f, "\nTotal tokens: {}", self.total)
+    }
+}
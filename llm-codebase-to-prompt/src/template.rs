@@ -0,0 +1,155 @@
+use crate::{language, render_changes, OutputFormat, Prompt, SourceFile};
+use minijinja::{context, Environment, UndefinedBehavior};
+use serde::Serialize;
+
+/// A file as templates see it.
+#[derive(Serialize)]
+struct FileView<'a> {
+    path: String,
+    /// The name it is shown under, with the line range of a part.
+    name: String,
+    language: Option<&'static str>,
+    contents: &'a str,
+    tokens: usize,
+    lines: usize,
+    /// The file as the output format writes it, header included.
+    rendered: String,
+}
+
+#[derive(Serialize)]
+struct SectionView<'a> {
+    name: &'a str,
+    context: &'a str,
+    files: Vec<FileView<'a>>,
+    tokens: usize,
+    /// Every file of the section as the output format writes them.
+    rendered: String,
+}
+
+#[derive(Serialize)]
+struct GitView<'a> {
+    /// The range as given, such as `main..HEAD`.
+    label: &'a str,
+    base: &'a str,
+    files: Vec<ChangeView>,
+    /// The unified diff, empty unless asked for.
+    diff: &'a str,
+    /// The changed files, diff and originals as the default layout writes them.
+    rendered: String,
+}
+
+#[derive(Serialize)]
+struct ChangeView {
+    path: String,
+    status: String,
+}
+
+/// Lays out `prompt` with a Jinja-like `template`.
+///
+/// The template sees:
+/// - `sections`: each with `name`, `context`, `tokens`, `rendered` and `files`;
+/// - `files`: the files of every section, each with `path`, `name`, `language`,
+///   `contents`, `tokens`, `lines` and `rendered`;
+/// - `tree`: the project tree, empty unless asked for;
+/// - `git`: `label`, `base`, `files` (`path`, `status`), `diff` and `rendered`, in
+///   git mode only;
+/// - `stats`: files, lines and tokens per language, the largest files and the total
+///   tokens, as a plain table;
+/// - `header`: the update note, statistics, tree and git changes as the default
+///   layout writes them;
+/// - `total_tokens`: the size of the prompt in the default layout;
+/// - `format`: the output format, such as `xml`.
+///
+/// # Errors
+/// Returns the template's syntax error, or an error from rendering it, such as an
+/// unknown variable.
+pub fn render(template: &str, prompt: &Prompt, format: OutputFormat) -> Result<String, String> {
+    let mut environment = Environment::new();
+    environment.set_undefined_behavior(UndefinedBehavior::Strict);
+    environment.set_keep_trailing_newline(true);
+    environment
+        .add_template("prompt", template)
+        .map_err(|e| describe(&e))?;
+
+    let sections: Vec<SectionView> = prompt
+        .sections
+        .iter()
+        .map(|section| {
+            let mut rendered = Vec::new();
+            let _ = format.write_files(&mut rendered, &section.files);
+            SectionView {
+                name: &section.name,
+                context: &section.context,
+                files: section
+                    .files
+                    .iter()
+                    .map(|file| file_view(file, format))
+                    .collect(),
+                tokens: section.files.iter().map(|file| file.tokens).sum(),
+                rendered: String::from_utf8_lossy(&rendered).into_owned(),
+            }
+        })
+        .collect();
+    let files: Vec<FileView> = prompt
+        .sections
+        .iter()
+        .flat_map(|section| &section.files)
+        .map(|file| file_view(file, format))
+        .collect();
+    let git = prompt.changes.as_ref().map(|changes| GitView {
+        label: &changes.label,
+        base: &changes.base,
+        files: changes
+            .files
+            .iter()
+            .map(|file| ChangeView {
+                path: file.path.to_string_lossy().into_owned(),
+                status: file.status.to_string(),
+            })
+            .collect(),
+        diff: &changes.patch,
+        rendered: render_changes(changes, format),
+    });
+
+    let format_name = serde_json::to_value(format)
+        .ok()
+        .and_then(|value| value.as_str().map(str::to_string));
+    let template = environment
+        .get_template("prompt")
+        .map_err(|e| describe(&e))?;
+    template
+        .render(context! {
+            sections,
+            files,
+            tree => prompt.tree,
+            stats => prompt.report.stats.to_string(),
+            git,
+            header => prompt.header,
+            total_tokens => prompt.report.total,
+            format => format_name,
+        })
+        .map_err(|e| describe(&e))
+}
+
+fn file_view(file: &SourceFile, format: OutputFormat) -> FileView<'_> {
+    FileView {
+        path: file.path.to_string_lossy().into_owned(),
+        name: file.display_name(),
+        language: language::detect(&file.path),
+        contents: &file.contents,
+        tokens: file.tokens,
+        lines: file.contents.lines().count(),
+        rendered: file.render(format),
+    }
+}
+
+/// The error, with where it happened, and the errors that caused it.
+fn describe(error: &minijinja::Error) -> String {
+    let mut description = error.to_string();
+    let mut source = std::error::Error::source(error);
+    while let Some(cause) = source {
+        description = format!("{description}: {cause}");
+        source = cause.source();
+    }
+    description
+}
@@ -0,0 +1,138 @@
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use std::fs;
+use std::path::Path;
+use tiktoken_rs::CoreBPE;
+
+/// Split pattern used for BPE files loaded from disk. This is the `cl100k_base`
+/// pattern, which is what most `.tiktoken` rank files in the wild were trained with.
+const BPE_PATTERN: &str = "(?i:'s|'t|'re|'ve|'m|'ll|'d)|[^\\r\\n\\p{L}\\p{N}]?\\p{L}+|\\p{N}{1,3}| ?[^\\s\\p{L}\\p{N}]+[\\r\\n]*|\\s*[\\r\\n]+|\\s+(?!\\S)|\\s+";
+
+/// An offline tokenizer used to count how many tokens a prompt will cost.
+pub enum Tokenizer {
+    /// A tiktoken-style byte pair encoding, either built in or loaded from a rank file.
+    Bpe(CoreBPE),
+    /// A Hugging Face `tokenizer.json`.
+    HuggingFace(Box<tokenizers::Tokenizer>),
+}
+
+impl Tokenizer {
+    /// Loads a tokenizer from a built-in encoding name (`cl100k_base`, `o200k_base`,
+    /// `p50k_base`, `r50k_base`) or from a local file. Files ending in `.json` are read
+    /// as Hugging Face tokenizers, anything else as a tiktoken BPE rank file.
+    ///
+    /// # Errors
+    /// Returns an error if the name is unknown or the file cannot be read or parsed.
+    pub fn load(spec: &str) -> Result<Self, String> {
+        let builtin = match spec {
+            "cl100k_base" => Some(tiktoken_rs::cl100k_base()),
+            "o200k_base" => Some(tiktoken_rs::o200k_base()),
+            "p50k_base" => Some(tiktoken_rs::p50k_base()),
+            "r50k_base" => Some(tiktoken_rs::r50k_base()),
+            _ => None,
+        };
+        if let Some(bpe) = builtin {
+            return bpe.map(Self::Bpe).map_err(|e| e.to_string());
+        }
+
+        let path = Path::new(spec);
+        if !path.is_file() {
+            return Err(format!(
+                "Unknown tokenizer [{spec}]: not a built-in encoding or a file"
+            ));
+        }
+        if path.extension().is_some_and(|ext| ext == "json") {
+            tokenizers::Tokenizer::from_file(path)
+                .map(|tokenizer| Self::HuggingFace(Box::new(tokenizer)))
+                .map_err(|e| format!("Error loading tokenizer [{spec}]: {e}"))
+        } else {
+            load_bpe_file(path).map(Self::Bpe)
+        }
+    }
+
+    /// Counts the tokens in `text`.
+    #[must_use]
+    pub fn count(&self, text: &str) -> usize {
+        match self {
+            Self::Bpe(bpe) => bpe.encode_ordinary(text).len(),
+            Self::HuggingFace(tokenizer) => tokenizer
+                .encode(text, false)
+                .map_or(0, |encoding| encoding.len()),
+        }
+    }
+}
+
+impl Default for Tokenizer {
+    fn default() -> Self {
+        Self::Bpe(tiktoken_rs::cl100k_base().expect("built-in cl100k_base encoding is valid"))
+    }
+}
+
+/// Reads a tiktoken rank file: one `<base64 token> <rank>` pair per line.
+fn load_bpe_file(path: &Path) -> Result<CoreBPE, String> {
+    use tiktoken_rs::Rank;
+
+    let contents = fs::read_to_string(path).map_err(|e| e.to_string())?;
+    let mut encoder = std::collections::HashMap::default();
+    for (number, line) in contents.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let parse_error = || {
+            format!(
+                "Invalid BPE rank file [{}] at line {}",
+                path.display(),
+                number + 1
+            )
+        };
+        let (token, rank) = line.split_once(' ').ok_or_else(parse_error)?;
+        let token = STANDARD.decode(token).map_err(|_| parse_error())?;
+        let rank: Rank = rank.trim().parse().map_err(|_| parse_error())?;
+        encoder.insert(token, rank);
+    }
+    CoreBPE::new(encoder, std::collections::HashMap::default(), BPE_PATTERN)
+        .map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fmt::Write;
+
+    #[test]
+    fn builtin_encodings_count_tokens() {
+        let tokenizer = Tokenizer::default();
+        assert_eq!(tokenizer.count(""), 0);
+        assert_eq!(tokenizer.count("hello world"), 2);
+    }
+
+    #[test]
+    fn unknown_names_are_rejected() {
+        let Err(error) = Tokenizer::load("no_such_encoding") else {
+            panic!("an unknown encoding loaded");
+        };
+        assert!(error.starts_with("Unknown tokenizer [no_such_encoding]"));
+    }
+
+    #[test]
+    fn rank_files_are_loaded_from_disk() {
+        let path =
+            std::env::temp_dir().join(format!("llm-prompt-tokens-{}.tiktoken", std::process::id()));
+        let mut ranks = String::new();
+        for byte in 0..=255_u8 {
+            let _ = writeln!(ranks, "{} {byte}", STANDARD.encode([byte]));
+        }
+        let _ = writeln!(ranks, "{} 256", STANDARD.encode("ab"));
+        fs::write(&path, ranks).unwrap();
+        let tokenizer = Tokenizer::load(&path.to_string_lossy()).unwrap();
+        assert_eq!(tokenizer.count("ab"), 1);
+        assert_eq!(tokenizer.count("abc"), 2);
+
+        fs::write(&path, "not base64\n").unwrap();
+        let Err(error) = Tokenizer::load(&path.to_string_lossy()) else {
+            panic!("an invalid rank file loaded");
+        };
+        assert!(error.ends_with("at line 1"));
+        fs::remove_file(&path).unwrap();
+    }
+}
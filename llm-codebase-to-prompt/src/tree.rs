@@ -0,0 +1,160 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::path::{Component, Path, PathBuf};
+
+/// Per-file details that can be shown next to each name in the tree.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum TreeAnnotation {
+    Size,
+    Lines,
+    Tokens,
+}
+
+/// How the project tree at the top of the prompt is drawn.
+#[derive(Clone, Debug, Default)]
+pub struct TreeOptions {
+    /// How many directory levels to draw; deeper directories are collapsed.
+    pub depth: Option<usize>,
+    /// Also list files that were walked but whose contents are not in the prompt.
+    pub show_excluded: bool,
+    pub annotations: Vec<TreeAnnotation>,
+}
+
+/// A file shown in the tree.
+pub struct TreeEntry {
+    pub path: PathBuf,
+    /// Whether the file's contents are in the prompt.
+    pub included: bool,
+    pub size: u64,
+    pub lines: Option<usize>,
+    pub tokens: Option<usize>,
+}
+
+enum Node<'a> {
+    Directory(BTreeMap<String, Self>),
+    File(&'a TreeEntry),
+}
+
+/// Draws `entries` as a `tree`-style listing rooted at `.`.
+#[must_use]
+pub fn render(entries: &[TreeEntry], options: &TreeOptions) -> String {
+    let mut root = BTreeMap::new();
+    for entry in entries
+        .iter()
+        .filter(|entry| entry.included || options.show_excluded)
+    {
+        insert(&mut root, &entry.path, entry);
+    }
+
+    let mut output = String::from(".\n");
+    draw(&root, "", 1, options, &mut output);
+    output
+}
+
+fn insert<'a>(root: &mut BTreeMap<String, Node<'a>>, path: &Path, entry: &'a TreeEntry) {
+    let names: Vec<String> = path
+        .components()
+        .filter_map(|component| match component {
+            Component::Normal(name) => Some(name.to_string_lossy().into_owned()),
+            _ => None,
+        })
+        .collect();
+    let Some((file_name, directories)) = names.split_last() else {
+        return;
+    };
+
+    let mut directory = root;
+    for name in directories {
+        let node = directory
+            .entry(name.clone())
+            .or_insert_with(|| Node::Directory(BTreeMap::new()));
+        directory = match node {
+            Node::Directory(children) => children,
+            Node::File(_) => return,
+        };
+    }
+    directory.insert(file_name.clone(), Node::File(entry));
+}
+
+fn draw(
+    directory: &BTreeMap<String, Node>,
+    prefix: &str,
+    depth: usize,
+    options: &TreeOptions,
+    output: &mut String,
+) {
+    let count = directory.len();
+    for (index, (name, node)) in directory.iter().enumerate() {
+        let last = index + 1 == count;
+        let (branch, indent) = if last {
+            ("└── ", "    ")
+        } else {
+            ("├── ", "│   ")
+        };
+        match node {
+            Node::File(entry) => {
+                let _ = writeln!(output, "{prefix}{branch}{name}{}", annotate(entry, options));
+            }
+            Node::Directory(children) => {
+                if options.depth.is_some_and(|max| depth >= max) {
+                    let files = count_files(children);
+                    let _ = writeln!(output, "{prefix}{branch}{name}/  ({files} files)");
+                } else {
+                    let _ = writeln!(output, "{prefix}{branch}{name}/");
+                    draw(
+                        children,
+                        &format!("{prefix}{indent}"),
+                        depth + 1,
+                        options,
+                        output,
+                    );
+                }
+            }
+        }
+    }
+}
+
+fn count_files(directory: &BTreeMap<String, Node>) -> usize {
+    directory
+        .values()
+        .map(|node| match node {
+            Node::File(_) => 1,
+            Node::Directory(children) => count_files(children),
+        })
+        .sum()
+}
+
+fn annotate(entry: &TreeEntry, options: &TreeOptions) -> String {
+    let mut details = Vec::new();
+    for annotation in &options.annotations {
+        match annotation {
+            TreeAnnotation::Size => details.push(human_size(entry.size)),
+            TreeAnnotation::Lines => {
+                details.extend(entry.lines.map(|lines| format!("{lines} lines")));
+            }
+            TreeAnnotation::Tokens => {
+                details.extend(entry.tokens.map(|tokens| format!("{tokens} tokens")));
+            }
+        }
+    }
+
+    let mut annotation = String::new();
+    if !details.is_empty() {
+        let _ = write!(annotation, "  [{}]", details.join(", "));
+    }
+    if !entry.included {
+        annotation.push_str("  (not included)");
+    }
+    annotation
+}
+
+fn human_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["B", "KiB", "MiB", "GiB"];
+    let mut size = bytes;
+    let mut unit = 0;
+    while size >= 1024 * 10 && unit + 1 < UNITS.len() {
+        size /= 1024;
+        unit += 1;
+    }
+    format!("{size} {}", UNITS[unit])
+}
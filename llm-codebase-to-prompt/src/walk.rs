@@ -0,0 +1,194 @@
+use ignore::{WalkBuilder, WalkState};
+use log::error;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, PoisonError};
+
+/// Name of the tool-specific ignore file, read with the same syntax as `.gitignore`.
+pub const PROMPT_IGNORE: &str = ".promptignore";
+
+/// Which ignore files are honored while walking the working directory.
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    Default,
+    PartialEq,
+    Eq,
+    clap::ValueEnum,
+    serde::Serialize,
+    serde::Deserialize,
+)]
+#[serde(rename_all = "kebab-case")]
+pub enum IgnoreMode {
+    /// Include every file, ignore files are not read. Hidden files still need `hidden`.
+    None,
+    /// Only the `.gitignore` and `.promptignore` at the root of the working directory,
+    /// plus the global git excludes.
+    Root,
+    /// Every nested `.gitignore`, `.ignore` and `.promptignore`, plus those of parent
+    /// directories, `.git/info/exclude` and the global git excludes.
+    #[default]
+    Recursive,
+}
+
+/// Builds a walker over `root` that applies `mode` and stops at `max_depth`. Hidden
+/// files and directories are skipped unless `hidden` is set; `.git` always is.
+#[must_use]
+pub fn builder(
+    root: &Path,
+    mode: IgnoreMode,
+    max_depth: Option<usize>,
+    hidden: bool,
+) -> WalkBuilder {
+    let mut builder = WalkBuilder::new(root);
+    builder
+        .max_depth(max_depth)
+        .require_git(false)
+        .hidden(!hidden);
+    if hidden {
+        builder.filter_entry(|entry| entry.file_name() != ".git");
+    }
+    match mode {
+        IgnoreMode::None => {
+            builder
+                .git_ignore(false)
+                .git_global(false)
+                .git_exclude(false)
+                .ignore(false)
+                .parents(false);
+        }
+        IgnoreMode::Root => {
+            builder
+                .git_ignore(false)
+                .git_global(true)
+                .git_exclude(false)
+                .ignore(false)
+                .parents(false);
+            for name in [".gitignore", PROMPT_IGNORE] {
+                let path = root.join(name);
+                if path.is_file() {
+                    if let Some(e) = builder.add_ignore(&path) {
+                        error!("Error reading ignore file [{}]: {e}", path.display());
+                    }
+                }
+            }
+        }
+        IgnoreMode::Recursive => {
+            builder
+                .git_ignore(true)
+                .git_global(true)
+                .git_exclude(true)
+                .ignore(true)
+                .parents(true)
+                .add_custom_ignore_filename(PROMPT_IGNORE);
+        }
+    }
+    builder
+}
+
+/// Walks `root` on every core and returns the path of every file that is not ignored,
+/// relative to `root`, starting with `./` and sorted.
+///
+/// # Errors
+/// Returns the first error met while walking.
+pub fn files(
+    root: &Path,
+    mode: IgnoreMode,
+    max_depth: Option<usize>,
+    hidden: bool,
+) -> Result<Vec<PathBuf>, String> {
+    let paths = Mutex::new(Vec::new());
+    let failure = Mutex::new(None);
+    builder(root, mode, max_depth, hidden)
+        .build_parallel()
+        .run(|| {
+            Box::new(|result| match result {
+                Ok(entry) => {
+                    // Follows symlinks, so a linked file is read like any other.
+                    if entry.path().is_file() {
+                        let path = entry.path();
+                        let path = Path::new(".").join(path.strip_prefix(root).unwrap_or(path));
+                        paths
+                            .lock()
+                            .unwrap_or_else(PoisonError::into_inner)
+                            .push(path);
+                    }
+                    WalkState::Continue
+                }
+                Err(e) => {
+                    error!("Error walking directory: {e}");
+                    failure
+                        .lock()
+                        .unwrap_or_else(PoisonError::into_inner)
+                        .get_or_insert(e.to_string());
+                    WalkState::Quit
+                }
+            })
+        });
+
+    if let Some(e) = failure.into_inner().unwrap_or_else(PoisonError::into_inner) {
+        return Err(e);
+    }
+    let mut paths = paths.into_inner().unwrap_or_else(PoisonError::into_inner);
+    paths.sort();
+    Ok(paths)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    /// An empty directory under the system's temporary one, unique to `name`.
+    fn scratch(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("llm-prompt-walk-{}-{name}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlinked_files_are_walked() {
+        let root = scratch("symlink");
+        fs::write(root.join("real.rs"), "fn a() {}\n").unwrap();
+        std::os::unix::fs::symlink(root.join("real.rs"), root.join("link.rs")).unwrap();
+        let paths = files(&root, IgnoreMode::None, None, false).unwrap();
+        assert_eq!(paths, [Path::new("./link.rs"), Path::new("./real.rs")]);
+    }
+
+    #[test]
+    fn hidden_files_need_hidden_and_git_is_always_skipped() {
+        let root = scratch("hidden");
+        fs::create_dir_all(root.join(".git")).unwrap();
+        fs::write(root.join(".git/config"), "").unwrap();
+        fs::write(root.join(".env"), "KEY=value\n").unwrap();
+        fs::write(root.join("main.rs"), "").unwrap();
+        assert_eq!(
+            files(&root, IgnoreMode::None, None, false).unwrap(),
+            [Path::new("./main.rs")]
+        );
+        assert_eq!(
+            files(&root, IgnoreMode::None, None, true).unwrap(),
+            [Path::new("./.env"), Path::new("./main.rs")]
+        );
+    }
+
+    #[test]
+    fn root_mode_reads_only_the_root_ignore_files() {
+        let root = scratch("root-mode");
+        fs::create_dir_all(root.join("sub")).unwrap();
+        fs::write(root.join(".gitignore"), "*.log\n").unwrap();
+        fs::write(root.join("sub/.gitignore"), "*.rs\n").unwrap();
+        fs::write(root.join("a.log"), "").unwrap();
+        fs::write(root.join("sub/b.rs"), "").unwrap();
+        assert_eq!(
+            files(&root, IgnoreMode::Root, None, false).unwrap(),
+            [Path::new("./sub/b.rs")]
+        );
+        assert!(files(&root, IgnoreMode::Recursive, None, false)
+            .unwrap()
+            .is_empty());
+    }
+}
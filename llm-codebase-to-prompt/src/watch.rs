@@ -0,0 +1,103 @@
+use crate::{Error, PromptBuilder};
+#[cfg(feature = "logging")]
+use log::info;
+use log::warn;
+use notify::{Event, EventKind, RecursiveMode, Watcher};
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, RecvTimeoutError};
+use std::time::Duration;
+
+/// Watches the root of `builder` and calls `rebuild` after every burst of changes to
+/// files the prompt is made of.
+///
+/// Events are gathered until none arrive for `debounce`. A changed file only counts
+/// if a section selects it under the same globs and ignore rules the prompt uses,
+/// before or after the change, so files that stop being ignored are picked up too.
+/// Paths for which `skip` is true, such as the prompt itself, never count. `rebuild`
+/// gets the files that changed, relative to the root.
+///
+/// # Errors
+/// Returns an error if the watcher cannot be started, the event channel closes, a
+/// pattern is invalid or the directory walk fails.
+pub fn watch(
+    builder: &PromptBuilder,
+    debounce: Duration,
+    skip: impl Fn(&Path) -> bool,
+    mut rebuild: impl FnMut(&[PathBuf]),
+) -> Result<(), Error> {
+    let root = builder.root().canonicalize()?;
+    let (tx, rx) = channel();
+    let mut watcher = notify::recommended_watcher(tx).map_err(|e| Error::Watch(e.to_string()))?;
+    watcher
+        .watch(&root, RecursiveMode::Recursive)
+        .map_err(|e| Error::Watch(e.to_string()))?;
+    #[cfg(feature = "logging")]
+    info!("Watching {}", root.display());
+
+    let mut selected = selected_files(builder)?;
+    loop {
+        let mut changed = BTreeSet::new();
+        let first = rx.recv().map_err(|e| Error::Watch(e.to_string()))?;
+        collect(first, &root, &skip, &mut changed);
+        loop {
+            match rx.recv_timeout(debounce) {
+                Ok(event) => collect(event, &root, &skip, &mut changed),
+                Err(RecvTimeoutError::Timeout) => break,
+                Err(RecvTimeoutError::Disconnected) => {
+                    return Err(Error::Watch("the file watcher stopped".to_string()))
+                }
+            }
+        }
+
+        let now = selected_files(builder)?;
+        let triggers: Vec<PathBuf> = changed
+            .into_iter()
+            .filter(|path| selected.contains(path) || now.contains(path))
+            .collect();
+        selected = now;
+        if !triggers.is_empty() {
+            #[cfg(feature = "logging")]
+            info!("Rebuilding after changes to {triggers:?}");
+            rebuild(&triggers);
+        }
+    }
+}
+
+/// Every file that some section includes.
+fn selected_files(builder: &PromptBuilder) -> Result<BTreeSet<PathBuf>, Error> {
+    Ok(builder
+        .list()?
+        .into_iter()
+        .flatten()
+        .filter(|(_, selection)| selection.is_included())
+        .map(|(path, _)| path)
+        .collect())
+}
+
+/// Adds the paths an event touched, in the `./path` form the directory walk gives.
+fn collect(
+    event: notify::Result<Event>,
+    root: &Path,
+    skip: &impl Fn(&Path) -> bool,
+    changed: &mut BTreeSet<PathBuf>,
+) {
+    let event = match event {
+        Ok(event) => event,
+        Err(e) => {
+            warn!("Watch error: {e}");
+            return;
+        }
+    };
+    if matches!(event.kind, EventKind::Access(_)) {
+        return;
+    }
+    for path in event.paths {
+        if skip(&path) {
+            continue;
+        }
+        if let Ok(relative) = path.strip_prefix(root) {
+            changed.insert(Path::new(".").join(relative));
+        }
+    }
+}